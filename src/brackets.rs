@@ -0,0 +1,96 @@
+//! Matching brackets.
+//!
+//! The bracket under the cursor, or just before it, is paired with the one
+//! that opens or closes it. Brackets in strings and comments, as styled by the
+//! highlighter of the language, are left out.
+
+use crate::buffer::{Buffer, Cursor};
+use crate::{get_matching_brace, is_close_bracket};
+
+/// Most lines searched for the matching bracket.
+const SEARCH_LINES: usize = 5000;
+
+const OPEN_BRACKETS: [char; 3] = ['(', '[', '{'];
+
+impl Buffer {
+    /// The bracket at `pos`, unless it is in a string or comment.
+    pub fn bracket_at(&self, pos: Cursor) -> Option<char> {
+        let c = self.highlighted_contents.get(pos.line)?.get(pos.idx)?;
+        let is_bracket = OPEN_BRACKETS.contains(&c.ch) || is_close_bracket(c.ch);
+        (is_bracket && !self.lang.is_literal(c.style)).then_some(c.ch)
+    }
+
+    /// Position of the bracket paired with the one at or before the cursor.
+    pub fn matching_bracket(&self) -> Option<Cursor> {
+        let at = self.cursor_pos;
+        if self.bracket_at(at).is_some() {
+            return self.bracket_pair(at);
+        }
+        self.bracket_pair(Cursor {
+            line: at.line,
+            idx: at.idx.checked_sub(1)?,
+        })
+    }
+
+    /// Position of the bracket paired with the one at `from`.
+    pub fn bracket_pair(&self, from: Cursor) -> Option<Cursor> {
+        let c = self.bracket_at(from)?;
+        let forward = !is_close_bracket(c);
+        let other = if forward {
+            get_matching_brace(c)
+        } else {
+            *OPEN_BRACKETS
+                .iter()
+                .find(|o| get_matching_brace(**o) == c)?
+        };
+        let mut depth = 0;
+        let mut pos = from;
+        loop {
+            pos = if forward {
+                if pos.idx + 1 < self.highlighted_contents[pos.line].len() {
+                    Cursor {
+                        idx: pos.idx + 1,
+                        ..pos
+                    }
+                } else {
+                    let line = (pos.line + 1..self.highlighted_contents.len())
+                        .find(|l| !self.highlighted_contents[*l].is_empty())?;
+                    Cursor { line, idx: 0 }
+                }
+            } else if pos.idx > 0 {
+                Cursor {
+                    idx: pos.idx - 1,
+                    ..pos
+                }
+            } else {
+                let line = (0..pos.line)
+                    .rev()
+                    .find(|l| !self.highlighted_contents[*l].is_empty())?;
+                Cursor {
+                    line,
+                    idx: self.highlighted_contents[line].len() - 1,
+                }
+            };
+            if pos.line.abs_diff(from.line) > SEARCH_LINES {
+                return None;
+            }
+            match self.bracket_at(pos) {
+                Some(b) if b == c => depth += 1,
+                Some(b) if b == other => {
+                    if depth == 0 {
+                        return Some(pos);
+                    }
+                    depth -= 1;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Moves the cursor to the matching bracket.
+    pub fn jump_to_bracket(&mut self) {
+        if let Some(pos) = self.matching_bracket() {
+            self.cursor_pos = pos;
+        }
+    }
+}
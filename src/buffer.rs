@@ -1,25 +1,57 @@
 //! Buffer and cursor handling module.
 
 use crate::autocomplete;
+use crate::config;
 use crate::direx;
+use crate::folds;
+use crate::fuzzy;
 use crate::languages;
+use crate::macros;
+use crate::marks;
+use crate::paths;
+use crate::prompt;
+use crate::registers;
+use crate::repeat;
+use crate::replace;
+use crate::search;
 use crate::snippets;
+use crate::visual::Select;
 use crossterm::{event, terminal};
 use std::{
     collections::HashMap,
     fmt::{self, Write},
-    fs, time,
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{self, BufRead},
+    path::Path,
+    time,
 };
 use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
+/// Style of the bracket matching the one at the cursor.
+const MATCH_STYLE: &str = "\x1b[1;43m\x1b[30m";
+/// Style of the summary of a closed fold.
+const FOLD_STYLE: &str = "\x1b[2;36m";
+
 pub fn savable(path: &str) -> bool {
     match path {
         "*scratch" => false,
         "*direx" => false,
+        "*search" => false,
+        "*replace" => false,
+        "*macros" => false,
         _ => true,
     }
 }
 
+/// Writes lines to a file the way buffers are saved, without trailing whitespace.
+pub fn write_lines(path: &str, lines: &[String]) -> io::Result<()> {
+    let trimmedlines: Vec<&str> = lines.iter().map(|s| s.trim_end()).collect();
+    let mut writecontent = trimmedlines.join("\n");
+    writecontent.push('\n');
+    fs::write(path, writecontent)
+}
+
 /// Prettifies events to string for printing.
 pub fn pretty_str_event(event: &event::Event) -> String {
     if let event::Event::Key(key) = event {
@@ -28,6 +60,8 @@ pub fn pretty_str_event(event: &event::Event) -> String {
         } else {
             format!("{} {}", key.modifiers, key.code)
         }
+    } else if let event::Event::Paste(text) = event {
+        format!("Paste ({} chars)", text.chars().count())
     } else {
         String::new()
     }
@@ -43,7 +77,7 @@ pub struct Cursor {
 }
 
 /// Enum for the mode of the program. Directly affects the behaviour of the program.
-#[derive(PartialEq, Eq, Clone, Copy)]
+#[derive(PartialEq, Eq, Hash, Clone, Copy)]
 pub enum Mode {
     /// Default mode.
     Default,
@@ -75,6 +109,20 @@ pub enum Mode {
     Switch,
     /// Run shell command mode.
     Command,
+    /// Path of a direx file operation.
+    DirexPath,
+    /// Confirmation of a direx file operation.
+    Confirm,
+    /// Fuzzy file finder mode.
+    FindFile,
+    /// Project search mode.
+    Grep,
+    /// Project replacement string mode.
+    ReplaceAll,
+    /// Visual selection mode.
+    Visual(Select),
+    /// Shell command to filter the selection through.
+    Filter,
 }
 
 impl Mode {
@@ -96,6 +144,8 @@ impl Mode {
             "nav" | "n" => Mode::Nav,
             "indent" | "i" => Mode::Indent,
             "command" | "cmd" | "sh" | "!" => Mode::Command,
+            "findfile" | "ff" => Mode::FindFile,
+            "grep" | "gr" => Mode::Grep,
             _ => Mode::Default,
         }
     }
@@ -105,6 +155,7 @@ impl Mode {
         use Mode::*;
         match self {
             Nav => "\x1b[46m\x1b[30m",
+            Visual(_) => "\x1b[45m\x1b[30m",
             _ => "\x1b[47m\x1b[30m",
         }
     }
@@ -128,6 +179,15 @@ impl fmt::Display for Mode {
             Mode::Indent => write!(f, "indent"),
             Mode::Switch => write!(f, "switch to mode"),
             Mode::Command => write!(f, "shell"),
+            Mode::DirexPath => write!(f, "path"),
+            Mode::Confirm => write!(f, "confirm (y/n)"),
+            Mode::FindFile => write!(f, "find file"),
+            Mode::Grep => write!(f, "grep (/ for regex)"),
+            Mode::ReplaceAll => write!(f, "replace all with"),
+            Mode::Visual(Select::Char) => write!(f, "visual :"),
+            Mode::Visual(Select::Line) => write!(f, "visual line :"),
+            Mode::Visual(Select::Block) => write!(f, "visual block :"),
+            Mode::Filter => write!(f, "filter through"),
         }
     }
 }
@@ -138,7 +198,8 @@ impl Mode {
         use Mode::*;
         match self {
             Default | Paste | Replace | Find | ReplaceStr | Tee => false,
-            Goto | Switch | OpenFile | Copy | Snippet | KillLines | Nav | Indent | Command => true,
+            Goto | Switch | OpenFile | Copy | Snippet | KillLines | Nav | Indent | Command
+            | DirexPath | Confirm | FindFile | Grep | ReplaceAll | Visual(_) | Filter => true,
         }
     }
 }
@@ -222,6 +283,24 @@ impl BufferHistory {
             })
             .collect()
     }
+
+    /// Points entries at or under `from` to `to` after a move.
+    pub fn rename(&mut self, from: &Path, to: &Path) {
+        for h in self.hist.iter_mut() {
+            if let Ok(rest) = direx::normalise(h).strip_prefix(from) {
+                *h = to.join(rest).display().to_string();
+            }
+        }
+    }
+
+    /// Drops entries at or under `path` after it was deleted.
+    pub fn remove(&mut self, path: &Path) {
+        let current = self.hist.get(self.head).cloned();
+        self.hist.retain(|h| !direx::normalise(h).starts_with(path));
+        self.head = current
+            .and_then(|c| self.hist.iter().position(|h| *h == c))
+            .unwrap_or(0);
+    }
 }
 
 /// Structure for storing the current displayed buffer.
@@ -244,22 +323,65 @@ pub struct Buffer {
     pub replace_str: String,
     /// Temporary buffer for all purposes.
     pub temp_str: String,
+    /// Char index of the cursor in `temp_str`, or `None` for its end.
+    pub prompt_cursor: Option<usize>,
+    /// Entries of `temp_str` by prompt mode, latest last.
+    pub prompt_history: HashMap<Mode, Vec<String>>,
+    /// Index of the recalled history entry.
+    pub history_idx: Option<usize>,
+    /// What was typed before recalling the history.
+    pub history_draft: String,
     /// Persistent buffer for all purposes.
     pub persistent_buffer: Vec<String>,
+    /// Named registers and recent kills.
+    pub registers: registers::Registers,
+    /// Keyboard macros.
+    pub macros: macros::Macros,
+    /// Marks and the jump list.
+    pub marks: marks::Marks,
+    /// Last change, repeated with `.`.
+    pub repeat: repeat::Repeat,
+    /// Closed folds.
+    pub folds: folds::Folds,
+    /// Number of edits so far, counted when the highlighting is updated after
+    /// the contents changed.
+    pub edits: usize,
+    /// File and hash of the contents when the highlighting was last updated.
+    pub edited: (String, u64),
     /// Current indent level. This is language agnostic.
     pub indent_lvl: usize,
     /// Current language. Used for determining indent size and highlighting.
     pub lang: Box<dyn languages::Language>,
     /// Current snippets used.
     pub snippets: Box<dyn snippets::Snippet>,
+    /// Tab stops of the last inserted snippet, if they are still being filled in.
+    pub snippet_session: Option<snippets::tabstop::SnippetSession>,
     /// Current autocomplete engine used.
     pub autocomplete: Box<dyn autocomplete::AutoComplete>,
     /// Local vars.
     pub vars: HashMap<String, BimVar>,
     /// Buffer history.
     pub buffer_history: BufferHistory,
+    /// Directory tree shown in `*direx`.
+    pub direx: direx::Direx,
+    /// Results shown in `*search`.
+    pub search: search::Search,
+    /// Replacement shown in `*replace`.
+    pub replace: replace::Replace,
+    /// Path to create once confirmed.
+    pub create_path: Option<String>,
     /// Alert message.
     pub alert: Alert,
+    /// Lines shown above the alert, such as picker candidates.
+    pub popup: Vec<Vec<languages::StyledChar>>,
+    /// Index of the selected picker candidate.
+    pub picker_idx: usize,
+    /// Whether the picker selection was moved since the picker was opened.
+    pub picker_moved: bool,
+    /// Project files, listed when the file finder is opened.
+    pub files: Vec<String>,
+    /// Where the visual selection started.
+    pub anchor: Cursor,
     /// Current mode.
     pub mode: Mode,
 }
@@ -267,12 +389,15 @@ pub struct Buffer {
 impl Buffer {
     /// Constructs a new instance of `Buffer` from a filepath.
     pub fn new(filepath: &str) -> Self {
+        let mut created = false;
         let contents: Vec<String> = fs::read_to_string(filepath)
-            .unwrap_or({
+            .unwrap_or_else(|_| {
                 if filepath != "*scratch" {
-                    _ = fs::File::create(filepath).map_err(|_| {
-                        println!("Illegal filepath, proceeding to scratch buffer...");
-                    });
+                    created = fs::File::create(filepath)
+                        .map_err(|_| {
+                            println!("Illegal filepath, proceeding to scratch buffer...");
+                        })
+                        .is_ok();
                 }
                 "\n".to_string()
             })
@@ -294,7 +419,7 @@ impl Buffer {
         } else {
             autocomplete::get_autocomplete_engine(filepath)
         };
-        let initvars = HashMap::from([
+        let mut initvars = HashMap::from([
             ("showbottombar".to_string(), BimVar::Bool(true)),
             (
                 "line-num-type".to_string(),
@@ -305,14 +430,40 @@ impl Buffer {
                 "ret-to-nav".to_string(),
                 BimVar::Bool(cfg!(feature = "nav-pro")),
             ),
+            (
+                "date-fmt".to_string(),
+                BimVar::Str(String::from("%Y-%m-%d")),
+            ),
+            ("time-fmt".to_string(), BimVar::Str(String::from("%H:%M"))),
+            (
+                "fold-method".to_string(),
+                BimVar::Str(String::from("indent")),
+            ),
         ]);
+        let (vars, ignored) = config::load_vars();
+        initvars.extend(vars);
+        let registers = if let Some(BimVar::Bool(true)) = initvars.get("persist-registers") {
+            registers::Registers::load()
+        } else {
+            registers::Registers::default()
+        };
         let highlighted_contents = lang.highlight(&contents);
-        let alert = Alert::new(&[], 1_000_000);
+        let alert = if ignored.is_empty() {
+            Alert::new(&[], 1_000_000)
+        } else {
+            Alert::new(
+                &[format!(
+                    "Ignored {} in ./.bim/config, commands can only be set in the user config",
+                    ignored.join(", ")
+                )],
+                5_000_000,
+            )
+        };
         let buffer_history = BufferHistory {
             hist: vec![filepath.to_string()],
             head: 0,
         };
-        Buffer {
+        let mut buf = Buffer {
             contents,
             highlighted_contents,
             iter_time: 0,
@@ -323,15 +474,48 @@ impl Buffer {
             find_str: String::new(),
             replace_str: String::new(),
             temp_str: String::new(),
+            prompt_cursor: None,
+            prompt_history: HashMap::from([(Mode::Command, prompt::load_history())]),
+            history_idx: None,
+            history_draft: String::new(),
             persistent_buffer: Vec::new(),
+            registers,
+            macros: macros::Macros::load(),
+            marks: marks::Marks::default(),
+            repeat: repeat::Repeat::default(),
+            folds: folds::Folds::default(),
+            edits: 0,
+            edited: (String::new(), 0),
             indent_lvl: 0,
             lang,
             snippets,
+            snippet_session: None,
             autocomplete,
             alert,
+            popup: Vec::new(),
+            picker_idx: 0,
+            picker_moved: false,
+            files: Vec::new(),
             buffer_history,
+            direx: direx::Direx::new(),
+            search: search::Search::default(),
+            replace: replace::Replace::default(),
+            create_path: None,
+            anchor: Cursor { line: 0, idx: 0 },
             mode: Mode::Nav,
+        };
+        buf.edited = buf.contents_hash();
+        if created && buf.apply_template() {
+            buf.reload_filetype();
         }
+        buf
+    }
+
+    /// The file and a hash of its contents, to tell edits apart.
+    fn contents_hash(&self) -> (String, u64) {
+        let mut hasher = DefaultHasher::new();
+        self.contents.hash(&mut hasher);
+        (self.filepath.clone(), hasher.finish())
     }
 
     #[inline]
@@ -348,6 +532,12 @@ impl Buffer {
     pub fn update_highlighting(&mut self) {
         if let Some(change) = self.vars.get_mut("changed") {
             *change = BimVar::Bool(true);
+            let edited = self.contents_hash();
+            // Opening or switching files isn't an edit.
+            if edited.0 == self.edited.0 && edited.1 != self.edited.1 {
+                self.edits += 1;
+            }
+            self.edited = edited;
             self.highlighted_contents = self.lang.highlight(&self.contents);
         }
     }
@@ -369,7 +559,7 @@ impl Buffer {
     pub fn move_left(&mut self) -> bool {
         if self.cursor_pos.idx == 0 {
             if self.cursor_pos.line != 0 {
-                self.cursor_pos.line -= 1;
+                self.cursor_pos.line = self.shown_line(self.cursor_pos.line - 1);
                 self.cursor_pos.idx = self.contents[self.cursor_pos.line].chars().count();
             } else {
                 return false;
@@ -387,8 +577,9 @@ impl Buffer {
         if self.cursor_pos.idx == self.contents[self.cursor_pos.line].chars().count()
             || self.contents[self.cursor_pos.line].is_empty()
         {
-            if self.cursor_pos.line + 1 != self.contents.len() && !self.contents.is_empty() {
-                self.cursor_pos.line += 1;
+            let next = self.next_shown(self.cursor_pos.line);
+            if next < self.contents.len() {
+                self.cursor_pos.line = next;
                 self.cursor_pos.idx = 0;
             } else {
                 return false;
@@ -400,11 +591,12 @@ impl Buffer {
     }
 
     /// Moves the cursor up, moving to the end of a line if the index is larger than the length of the line.
+    /// Closed folds are skipped.
     /// Return value signifies whether the cursor actually moved.
     #[inline]
     pub fn move_up(&mut self) -> bool {
         if self.cursor_pos.line != 0 {
-            self.cursor_pos.line -= 1;
+            self.cursor_pos.line = self.shown_line(self.cursor_pos.line - 1);
             if self.cursor_pos.idx >= self.contents[self.cursor_pos.line].chars().count() {
                 self.cursor_pos.idx = self.contents[self.cursor_pos.line].chars().count();
             }
@@ -415,11 +607,13 @@ impl Buffer {
     }
 
     /// Moves the cursor down, moving to the end of a line if the index is larger than the length of a line.
+    /// Closed folds are skipped.
     /// Return value signfies whether the cursor actually moved.
     #[inline]
     pub fn move_down(&mut self) -> bool {
-        if self.cursor_pos.line + 1 != self.contents.len() && !self.contents.is_empty() {
-            self.cursor_pos.line += 1;
+        let next = self.next_shown(self.cursor_pos.line);
+        if next < self.contents.len() {
+            self.cursor_pos.line = next;
             if self.cursor_pos.idx > self.contents[self.cursor_pos.line].chars().count() {
                 self.cursor_pos.idx = self.contents[self.cursor_pos.line].chars().count();
             }
@@ -435,11 +629,7 @@ impl Buffer {
             if let Some(BimVar::Bool(changed)) = self.vars.get_mut("changed") {
                 if *changed {
                     *changed = false;
-                    let trimmedlines: Vec<&str> =
-                        self.contents.iter().map(|s| s.trim_end()).collect();
-                    let mut writecontent = trimmedlines.join("\n");
-                    writecontent.push('\n');
-                    _ = fs::write(&self.filepath, writecontent);
+                    _ = write_lines(&self.filepath, &self.contents);
                     self.alert = Alert::new(&["save".to_string()], 1_000_000);
                     self.add_tokens();
                 }
@@ -524,17 +714,224 @@ impl Buffer {
         self.update_highlighting();
     }
 
+    /// Ranks the available snippets against `temp_str`.
+    /// A query that expands to a snippet by itself comes first.
+    pub fn snippet_candidates(&self) -> Vec<fuzzy::Candidate> {
+        let query = self.temp_str.trim();
+        let list = self.snippets.list();
+        let mut items = vec![];
+        if !query.is_empty() && !list.iter().any(|(t, _)| t == query) {
+            let expansion = self.snippets.query(query);
+            if expansion.iter().any(|l| !l.is_empty()) {
+                let (lines, _) = snippets::tabstop::parse(&expansion);
+                items.push((query.to_string(), lines[0].trim().to_string()));
+            }
+        }
+        items.extend(list);
+        let mut candidates = fuzzy::rank(query, items.into_iter());
+        if let Some(exact) = candidates.iter().position(|c| c.text == query) {
+            let c = candidates.remove(exact);
+            candidates.insert(0, c);
+        }
+        candidates
+    }
+
+    /// Answers the confirmation of the pending operation of the current buffer.
+    pub fn confirm(&mut self, yes: bool) {
+        if let Some(path) = self.create_path.take() {
+            if yes {
+                self.open_file(&path);
+            } else {
+                self.alert = Alert::new(&["Cancelled".to_string()], 1_000_000);
+            }
+        } else if self.filepath == "*replace" {
+            self.replace_apply(yes);
+        } else {
+            self.direx_confirm(yes);
+        }
+    }
+
+    /// Opens the file finder over the project files.
+    pub fn find_file(&mut self) {
+        self.files = direx::get_dirs();
+        self.mode = Mode::FindFile;
+        self.temp_str.clear();
+        self.picker_idx = 0;
+        self.picker_moved = false;
+    }
+
+    /// Opens the snippet picker.
+    pub fn snippet_prompt(&mut self) {
+        self.mode = Mode::Snippet;
+        self.temp_str.clear();
+        self.picker_idx = 0;
+        self.picker_moved = false;
+    }
+
+    /// Project files ranked against `temp_str`.
+    pub fn file_candidates(&self) -> Vec<fuzzy::Candidate> {
+        fuzzy::rank(
+            self.temp_str.trim(),
+            self.files.iter().map(|f| (f.clone(), String::new())),
+        )
+    }
+
+    /// Updates `popup` for the current mode.
+    pub fn update_popup(&mut self) {
+        self.popup = match self.mode {
+            Mode::Snippet => {
+                let candidates = self.snippet_candidates();
+                if candidates.is_empty() {
+                    self.picker_idx = 0;
+                    vec![]
+                } else {
+                    self.picker_idx %= candidates.len();
+                    let mut popup = fuzzy::render(&candidates, self.picker_idx, 8);
+                    let expansion = self.snippets.query(&candidates[self.picker_idx].text);
+                    let (lines, _) = snippets::tabstop::parse(&expansion);
+                    for l in lines.iter().take(6) {
+                        popup.push(languages::StyledChar::colour_string(
+                            &format!("  | {l}"),
+                            "\x1b[2m",
+                        ));
+                    }
+                    popup
+                }
+            }
+            Mode::FindFile => {
+                let candidates = self.file_candidates();
+                if candidates.is_empty() {
+                    self.picker_idx = 0;
+                    vec![]
+                } else {
+                    self.picker_idx %= candidates.len();
+                    let mut popup = fuzzy::render(&candidates, self.picker_idx, 8);
+                    if let Ok(file) = fs::File::open(&candidates[self.picker_idx].text) {
+                        for l in io::BufReader::new(file)
+                            .lines()
+                            .take(6)
+                            .map_while(Result::ok)
+                        {
+                            popup.push(languages::StyledChar::colour_string(
+                                &format!("  | {}", l.replace('\t', "    ")),
+                                "\x1b[2m",
+                            ));
+                        }
+                    }
+                    popup
+                }
+            }
+            Mode::OpenFile => paths::render(&paths::completions(&paths::expand(&self.temp_str))),
+            Mode::Confirm if self.create_path.is_some() => vec![],
+            Mode::Confirm if self.filepath == "*replace" => {
+                vec![languages::StyledChar::colour_string(
+                    &self.replace.summary(),
+                    "\x1b[1m",
+                )]
+            }
+            Mode::Confirm => match &self.direx.pending {
+                Some(direx::Op::Apply(changes)) => {
+                    let mut popup = direx::edit::preview(changes);
+                    popup.push(languages::StyledChar::colour_string(
+                        "Apply these changes? (y/n)",
+                        "\x1b[1m",
+                    ));
+                    popup
+                }
+                _ => vec![],
+            },
+            _ => vec![],
+        };
+    }
+
+    /// Replaces the contents of a newly created file with the template for it, if any.
+    /// Return value signifies whether a template was applied.
+    pub fn apply_template(&mut self) -> bool {
+        let Some((template, shell)) = snippets::get_template(&self.filepath) else {
+            return false;
+        };
+        let (lines, stops) = snippets::tabstop::parse(&snippets::vars::expand(
+            &template,
+            &self.snippet_context(shell),
+        ));
+        self.contents = lines;
+        if self.contents.is_empty() {
+            self.contents.push(String::new());
+        }
+        self.cursor_pos = Cursor { line: 0, idx: 0 };
+        self.update_highlighting();
+        self.start_snippet_session(stops);
+        true
+    }
+
     pub fn reload_file(&mut self) {
+        self.snippet_session = None;
         if self.filepath == *"*direx" {
-            self.contents = direx::get_dirs();
+            self.direx.editing = None;
+            self.direx.refresh();
+            self.contents = self.direx.lines();
+        } else if self.filepath == *"*search" {
+            self.contents = self.search.lines();
+        } else if self.filepath == *"*replace" {
+            self.contents = self.replace.lines();
+        } else if self.filepath == *"*macros" {
+            self.contents = self.macros.lines();
         } else {
+            let created = savable(&self.filepath) && !Path::new(&self.filepath).exists();
             self.contents = fs::read_to_string(&self.filepath)
                 .unwrap_or("\n".to_string())
                 .lines()
                 .map(|s| s.to_string())
                 .collect();
+            if created {
+                self.apply_template();
+            }
             self.save();
         }
+        self.reload_filetype();
+    }
+
+    /// Saves the current file and opens `path`, adding it to the buffer history.
+    pub fn open_file(&mut self, path: &str) {
+        self.push_jump();
+        self.save();
+        self.buffer_history.hist.push(path.to_string());
+        self.buffer_history.head = self.buffer_history.hist.len() - 1;
+        self.filepath = path.to_string();
+        self.reload_file();
+        if self.cursor_pos.line >= self.contents.len() {
+            self.cursor_pos.line = self.contents.len() - 1;
+        }
+        if self.cursor_pos.idx > self.contents[self.cursor_pos.line].chars().count() {
+            self.cursor_pos.idx = self.contents[self.cursor_pos.line].chars().count();
+        }
+        self.update_highlighting();
+    }
+
+    /// Switches to a buffer that isn't a file, like `*direx`,
+    /// adding it to the buffer history if it isn't there yet.
+    pub fn open_special(&mut self, name: &str) {
+        self.push_jump();
+        self.switch_buffer(name);
+    }
+
+    /// Saves the current file and shows `path`, which may be a special buffer,
+    /// adding it to the buffer history if it isn't there yet.
+    pub fn switch_buffer(&mut self, name: &str) {
+        self.save();
+        match self.buffer_history.hist.iter().position(|x| x == name) {
+            Some(i) => self.buffer_history.head = i,
+            None => {
+                self.buffer_history.hist.push(name.to_string());
+                self.buffer_history.head = self.buffer_history.hist.len() - 1;
+            }
+        }
+        self.filepath = name.to_string();
+        self.reload_file();
+    }
+
+    /// Detects the language and snippets from the filepath or a `use-ext:` line.
+    pub fn reload_filetype(&mut self) {
         self.lang = if self.contents[0].contains("use-ext:") {
             languages::get_lang(&self.contents[0])
         } else {
@@ -562,16 +959,22 @@ impl Buffer {
             bottom_pad += 1;
         }
         bottom_pad += self.alert.contents.len();
-        if self.cursor_pos.line > self.top + height - bottom_pad - 3 {
-            self.top = self.cursor_pos.line + bottom_pad + 3 - height;
+        // The popup gets what is left of a short terminal.
+        let popup_len = self.popup.len().min(height.saturating_sub(bottom_pad + 4));
+        bottom_pad += popup_len;
+        self.top = self.shown_line(self.top);
+        let rows = height.saturating_sub(bottom_pad + 3);
+        if self.cursor_pos.line > self.top
+            && self.shown_between(self.top, self.cursor_pos.line) > rows
+        {
+            self.top = self.shown_above(self.cursor_pos.line, rows);
         }
 
         let top_pad = 3;
-        if self.cursor_pos.line < self.top + top_pad && self.cursor_pos.line >= top_pad {
-            self.top = self.cursor_pos.line - 3;
-        }
-        if self.cursor_pos.line <= top_pad {
-            self.top = 0;
+        if self.cursor_pos.line < self.top
+            || self.shown_between(self.top, self.cursor_pos.line) < top_pad
+        {
+            self.top = self.shown_above(self.cursor_pos.line, top_pad);
         }
         let mut tb_printed = String::new();
 
@@ -583,6 +986,7 @@ impl Buffer {
         }
 
         let content = &self.highlighted_contents;
+        let bracket = self.matching_bracket();
         let indent_size = self.lang.indent_size();
         let spaces = 2;
         let mut sidesize = spaces;
@@ -609,8 +1013,11 @@ impl Buffer {
 
         let mut linectr = self.top;
         let mut linesprinted = 0;
-        while linectr < self.top + height - bottom_pad && linectr < content.len() {
+        while linesprinted + bottom_pad < height && linectr < content.len() {
             linesprinted += 1;
+            let fold = self
+                .fold_end(linectr)
+                .map(|end| folds::summary(end - linectr));
             if linetype != LineNumType::None {
                 if linectr == self.cursor_pos.line {
                     _ = write!(
@@ -619,19 +1026,11 @@ impl Buffer {
                         linectr + 1
                     );
                 } else if linetype == LineNumType::Relative {
-                    if linectr > self.cursor_pos.line {
-                        _ = write!(
-                            &mut tb_printed,
-                            "\x1b[2m\x1b[36m{: >numsize$}  \x1b[0m",
-                            linectr - self.cursor_pos.line
-                        );
-                    } else {
-                        _ = write!(
-                            &mut tb_printed,
-                            "\x1b[2m\x1b[36m{: >numsize$}  \x1b[0m",
-                            self.cursor_pos.line - linectr
-                        );
-                    }
+                    _ = write!(
+                        &mut tb_printed,
+                        "\x1b[2m\x1b[36m{: >numsize$}  \x1b[0m",
+                        self.shown_between(linectr, self.cursor_pos.line)
+                    );
                 } else {
                     _ = write!(
                         &mut tb_printed,
@@ -666,6 +1065,14 @@ impl Buffer {
                                 ctnt.ch
                             );
                         }
+                        m if bracket
+                            == Some(Cursor {
+                                line: linectr,
+                                idx: m,
+                            }) =>
+                        {
+                            _ = write!(&mut tb_printed, "{MATCH_STYLE}{}\x1b[0m", ctnt.ch);
+                        }
                         b if b == id => {
                             if ctnt.ch == ' ' {
                                 _ = write!(&mut tb_printed, "\x1b[2;33m|\x1b[0m");
@@ -673,6 +1080,12 @@ impl Buffer {
                                 _ = write!(&mut tb_printed, "\x1b[33m{}\x1b[0m", ctnt.ch);
                             }
                         }
+                        p if self.in_placeholder(linectr, p) => {
+                            _ = write!(&mut tb_printed, "\x1b[4m{ctnt}");
+                        }
+                        s if self.in_selection(linectr, s) => {
+                            _ = write!(&mut tb_printed, "\x1b[7m{ctnt}");
+                        }
                         _c if wi > ruler_idx => {
                             if ctnt.ch == ' ' {
                                 _ = write!(&mut tb_printed, "\x1b[2;31m|\x1b[0m");
@@ -686,15 +1099,29 @@ impl Buffer {
                     }
                     i += 1;
                 }
+                // Columns taken by the fold summary, which isn't part of the line.
+                let mut summary_width = 0;
+                if let Some(summary) = &fold {
+                    if i == self.cursor_pos.idx && wi < truewidth {
+                        _ = write!(&mut tb_printed, "{} \x1b[0m", self.mode.cursor_style());
+                        i += 1;
+                        wi += 1;
+                    }
+                    if wi + summary.len() <= truewidth {
+                        _ = write!(&mut tb_printed, "{FOLD_STYLE}{summary}\x1b[0m");
+                        summary_width = summary.len();
+                        wi += summary.len();
+                    }
+                }
                 while wi < truewidth {
                     match i {
                         a if a == self.cursor_pos.idx => {
                             _ = write!(&mut tb_printed, "{} \x1b[0m", self.mode.cursor_style(),);
                         }
-                        b if b == id => {
+                        b if b + summary_width == id => {
                             tb_printed.push_str("\x1b[2;33m|\x1b[0m");
                         }
-                        c if c == ruler_idx => {
+                        c if c + summary_width == ruler_idx => {
                             tb_printed.push_str("\x1b[2;31m|\x1b[0m");
                         }
                         _ => {
@@ -709,13 +1136,31 @@ impl Buffer {
                 }
             } else {
                 let mut wi = 0;
-                'pl: for c in content[linectr].iter() {
+                'pl: for (i, c) in content[linectr].iter().enumerate() {
                     wi += c.ch.width_cjk().unwrap_or(0);
                     if wi > truewidth {
                         break 'pl;
                     }
+                    if bracket
+                        == Some(Cursor {
+                            line: linectr,
+                            idx: i,
+                        })
+                    {
+                        _ = write!(&mut tb_printed, "{MATCH_STYLE}{}\x1b[0m", c.ch);
+                        continue;
+                    }
+                    if self.in_selection(linectr, i) {
+                        tb_printed.push_str("\x1b[7m");
+                    }
                     _ = write!(&mut tb_printed, "{c}");
                 }
+                if let Some(summary) = &fold
+                    && wi + summary.len() <= truewidth
+                {
+                    _ = write!(&mut tb_printed, "{FOLD_STYLE}{summary}\x1b[0m");
+                    wi += summary.len();
+                }
                 while wi < truewidth {
                     tb_printed.push(' ');
                     wi += 1;
@@ -724,7 +1169,7 @@ impl Buffer {
                     tb_printed.push('\n');
                 }
             }
-            linectr += 1;
+            linectr = self.next_shown(linectr);
         }
 
         while linesprinted + bottom_pad < height {
@@ -737,6 +1182,24 @@ impl Buffer {
             linesprinted += 1;
         }
 
+        for line in self.popup.iter().take(popup_len) {
+            let mut wi = 0;
+            'pl: for c in line.iter() {
+                wi += c.ch.width_cjk().unwrap_or(0);
+                if wi >= width {
+                    break 'pl;
+                }
+                _ = write!(&mut tb_printed, "{c}");
+            }
+            while wi < width {
+                tb_printed.push(' ');
+                wi += 1;
+            }
+            if cfg!(target_os = "windows") {
+                tb_printed.push('\n');
+            }
+        }
+
         let mut ctr = 0;
         'count: for line in self.alert.contents.iter() {
             if ctr > 16 {
@@ -803,18 +1266,20 @@ impl Buffer {
             } else {
                 _ = write!(&mut tb_printed, "{bottom_bar}\x1b[0m | ");
             }
+            let (prompt, escapes) = match self.mode {
+                Mode::Nav | Mode::Visual(_) => (format!(": {}", self.temp_str), 0),
+                m if m.show_temp() => {
+                    let escapes = m.cursor_style().len() + "\x1b[0m".len();
+                    (format!(": {}", self.render_prompt()), escapes)
+                }
+                _ => (String::new(), 0),
+            };
+            // The escapes of the prompt cursor don't take up any width.
             _ = write!(
                 &mut tb_printed,
-                "{: <width$}",
-                format!(
-                    "{}{}",
-                    self.mode,
-                    if self.mode.show_temp() {
-                        format!(": {}", self.temp_str)
-                    } else {
-                        String::new()
-                    }
-                )
+                "{: <w$}",
+                format!("{}{prompt}", self.mode),
+                w = width + escapes
             );
         } else {
             tb_printed.pop();
@@ -0,0 +1,160 @@
+//! System clipboard.
+//!
+//! Copying goes through the `clipboard-copy` command if it is set in the
+//! config (like `wl-copy`, `xclip -selection clipboard` or `pbcopy`), and
+//! through an OSC 52 escape sequence otherwise, which most terminals support
+//! over SSH too. Pasting needs the `clipboard-paste` command (like `wl-paste -n`,
+//! `xclip -selection clipboard -o` or `pbpaste`), as reading the clipboard with
+//! OSC 52 is rarely allowed.
+//!
+//! Text pasted into the terminal arrives as a single bracketed paste, which is
+//! inserted as it is instead of being typed.
+
+use crate::buffer::{Alert, BimVar, Buffer, Mode};
+use std::{
+    env,
+    io::{self, Write},
+    process::Stdio,
+};
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(data: &[u8]) -> String {
+    let mut ret = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                ret.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                ret.push('=');
+            }
+        }
+    }
+    ret
+}
+
+/// Sets the clipboard with an OSC 52 escape sequence, passed through tmux if needed.
+fn osc52(text: &str) {
+    let seq = format!("\x1b]52;c;{}\x07", base64(text.as_bytes()));
+    if env::var("TMUX").is_ok() {
+        print!("\x1bPtmux;{}\x1b\\", seq.replace('\x1b', "\x1b\x1b"));
+    } else {
+        print!("{seq}");
+    }
+    _ = io::stdout().flush();
+}
+
+fn run_copy(cmd: &str, text: &str) -> Result<(), String> {
+    let mut child = crate::shell_command(cmd)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("{cmd}: {e}"))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(text.as_bytes())
+            .map_err(|e| format!("{cmd}: {e}"))?;
+    }
+    match child.wait() {
+        Ok(s) if s.success() => Ok(()),
+        Ok(s) => Err(format!("{cmd}: {s}")),
+        Err(e) => Err(format!("{cmd}: {e}")),
+    }
+}
+
+impl Buffer {
+    fn clipboard_var(&self, name: &str) -> Option<String> {
+        match self.vars.get(name) {
+            Some(BimVar::Str(s)) if !s.is_empty() => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+    /// Whether every copy also goes to the clipboard.
+    pub fn clipboard_sync(&self) -> bool {
+        matches!(self.vars.get("clipboard-sync"), Some(BimVar::Bool(true)))
+    }
+
+    /// Copies lines to the system clipboard.
+    pub fn copy_to_clipboard(&mut self, lines: &[String]) {
+        let text = lines.join("\n");
+        match self.clipboard_var("clipboard-copy") {
+            Some(cmd) => {
+                if let Err(e) = run_copy(&cmd, &text) {
+                    self.alert = Alert::new(&[e], 3_000_000);
+                }
+            }
+            None => osc52(&text),
+        }
+    }
+
+    /// Inserts text at the cursor as it is, leaving the cursor after it.
+    pub fn insert_text(&mut self, text: &str) {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        let line = &mut self.contents[self.cursor_pos.line];
+        let at = line
+            .char_indices()
+            .nth(self.cursor_pos.idx)
+            .map_or(line.len(), |(i, _)| i);
+        let tail = line.split_off(at);
+        let mut parts = text.split('\n');
+        if let Some(first) = parts.next() {
+            line.push_str(first);
+            self.cursor_pos.idx += first.chars().count();
+        }
+        for part in parts {
+            self.cursor_pos.line += 1;
+            self.contents.insert(self.cursor_pos.line, part.to_string());
+            self.cursor_pos.idx = part.chars().count();
+        }
+        self.contents[self.cursor_pos.line].push_str(&tail);
+        self.update_highlighting();
+    }
+
+    /// Handles text pasted into the terminal. Prompts get its first line.
+    pub fn paste_event(&mut self, text: &str) {
+        let first = text.lines().next().unwrap_or("");
+        match self.mode {
+            Mode::Find => self.find_str.push_str(first),
+            Mode::ReplaceStr => self.replace_str.push_str(first),
+            Mode::Confirm => {}
+            Mode::Nav => self.insert_text(text),
+            Mode::Visual(_) => {
+                self.mode = Mode::Nav;
+                self.insert_text(text);
+            }
+            m if m.show_temp() => {
+                for c in first.chars() {
+                    self.prompt_insert(c);
+                }
+                self.picker_idx = 0;
+            }
+            _ => self.insert_text(text),
+        }
+    }
+
+    /// Inserts the system clipboard at the cursor.
+    pub fn paste_clipboard(&mut self) {
+        let Some(cmd) = self.clipboard_var("clipboard-paste") else {
+            self.alert = Alert::new(
+                &["Set clipboard-paste in the config to paste from the clipboard".to_string()],
+                3_000_000,
+            );
+            return;
+        };
+        match crate::shell_command(&cmd).stderr(Stdio::null()).output() {
+            Ok(o) if o.status.success() => {
+                self.insert_text(&String::from_utf8_lossy(&o.stdout));
+            }
+            Ok(o) => self.alert = Alert::new(&[format!("{cmd}: {}", o.status)], 3_000_000),
+            Err(e) => self.alert = Alert::new(&[format!("{cmd}: {e}")], 3_000_000),
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Configuration directories.
+//!
+//! Configuration is looked up in the project directory (`./.bim`) first and
+//! then in the user directory, which is `$BIM_CONFIG`, `$XDG_CONFIG_HOME/bim`
+//! or `~/.config/bim` (`%APPDATA%\bim` on Windows).
+//!
+//! The `config` file in a configuration directory sets buffer variables with
+//! lines of `name = value`. Lines starting with `#` are ignored.
+
+use crate::buffer::BimVar;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// Name of the project configuration directory.
+pub const PROJECT_DIR: &str = ".bim";
+
+/// Returns the user configuration directory.
+pub fn user_dir() -> Option<PathBuf> {
+    if let Ok(dir) = env::var("BIM_CONFIG") {
+        return Some(PathBuf::from(dir));
+    }
+    if let Ok(dir) = env::var("XDG_CONFIG_HOME") {
+        return Some(PathBuf::from(dir).join("bim"));
+    }
+    if cfg!(target_os = "windows") {
+        env::var("APPDATA")
+            .ok()
+            .map(|d| PathBuf::from(d).join("bim"))
+    } else {
+        env::var("HOME")
+            .ok()
+            .map(|d| PathBuf::from(d).join(".config").join("bim"))
+    }
+}
+
+/// Returns the configuration directories, most specific first.
+pub fn dirs() -> Vec<PathBuf> {
+    let mut ret = vec![PathBuf::from(PROJECT_DIR)];
+    if let Some(dir) = user_dir() {
+        ret.push(dir);
+    }
+    ret
+}
+
+/// Whether a configuration file is in the project directory. Those come with
+/// the project rather than from the user, so they aren't trusted to run commands.
+pub fn in_project(path: &Path) -> bool {
+    path.starts_with(PROJECT_DIR)
+}
+
+/// Returns the files named `name` in the `sub` directory of every configuration directory,
+/// most specific first. Only existing files are returned.
+pub fn files(sub: &str, name: &str) -> Vec<PathBuf> {
+    dirs()
+        .into_iter()
+        .map(|d| d.join(sub).join(name))
+        .filter(|p| p.is_file())
+        .collect()
+}
+
+/// Gets the extension of a path, or of a `use-ext:` line.
+pub fn extension(path: &str) -> Option<&str> {
+    let (_, ext) = path.trim().rsplit_once('.')?;
+    if ext.is_empty() || ext.contains(['/', '\\']) {
+        None
+    } else {
+        Some(ext)
+    }
+}
+
+/// Variables holding shell commands, which run whenever they are used.
+const COMMAND_VARS: [&str; 2] = ["clipboard-copy", "clipboard-paste"];
+
+/// Reads variables from the `config` files. Project variables override user
+/// variables, except that `COMMAND_VARS` are only read from the user directory.
+/// The names of those the project tried to set are returned too.
+pub fn load_vars() -> (Vec<(String, BimVar)>, Vec<String>) {
+    let mut ret = vec![];
+    let mut ignored = vec![];
+    for dir in dirs().iter().rev() {
+        let Ok(contents) = fs::read_to_string(dir.join("config")) else {
+            continue;
+        };
+        for line in contents.lines() {
+            if line.trim_start().starts_with('#') {
+                continue;
+            }
+            if let Some((name, value)) = line.split_once('=') {
+                if in_project(dir) && COMMAND_VARS.contains(&name.trim()) {
+                    ignored.push(name.trim().to_string());
+                    continue;
+                }
+                let var = match value.trim() {
+                    "true" => BimVar::Bool(true),
+                    "false" => BimVar::Bool(false),
+                    v => BimVar::Str(v.to_string()),
+                };
+                ret.push((name.trim().to_string(), var));
+            }
+        }
+    }
+    (ret, ignored)
+}
@@ -0,0 +1,385 @@
+//! Editing the `*direx` listing as text.
+//!
+//! While editing, every entry is shown as its id followed by its path, like
+//! `03 src/main.rs`. Changing the path moves the entry, removing the line deletes
+//! it, duplicating the line copies it and a line without an id creates a file,
+//! or a directory if it ends with `/`. Applying the edits shows a preview of the
+//! changes before performing them.
+
+use super::{copy_rec, create_parent};
+use crate::buffer::{Alert, Buffer, Mode};
+use crate::languages::StyledChar;
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+/// A change made by editing the listing.
+pub enum Change {
+    /// Create a file, or a directory if the bool is true.
+    Create(PathBuf, bool),
+    Move(PathBuf, PathBuf),
+    Copy(PathBuf, PathBuf),
+    Delete(PathBuf),
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Change::Create(p, true) => write!(f, "create {}/", p.display()),
+            Change::Create(p, false) => write!(f, "create {}", p.display()),
+            Change::Move(from, to) => write!(f, "move   {} -> {}", from.display(), to.display()),
+            Change::Copy(from, to) => write!(f, "copy   {} -> {}", from.display(), to.display()),
+            Change::Delete(p) => write!(f, "delete {}", p.display()),
+        }
+    }
+}
+
+impl Change {
+    fn colour(&self) -> &'static str {
+        match self {
+            Change::Create(..) => "\x1b[32m",
+            Change::Move(..) => "\x1b[33m",
+            Change::Copy(..) => "\x1b[36m",
+            Change::Delete(..) => "\x1b[31m",
+        }
+    }
+}
+
+/// Renders the listing for editing.
+pub fn lines(entries: &[(PathBuf, bool)]) -> Vec<String> {
+    let width = entries.len().saturating_sub(1).to_string().len();
+    let mut ret: Vec<String> = entries
+        .iter()
+        .enumerate()
+        .map(|(id, (path, is_dir))| {
+            let slash = if *is_dir { "/" } else { "" };
+            format!("{id:0width$} {}{slash}", path.display())
+        })
+        .collect();
+    if ret.is_empty() {
+        ret.push(String::new());
+    }
+    ret
+}
+
+/// Parses an edited line into its id, if any, and its path.
+fn parse_line(line: &str, len: usize) -> (Option<usize>, &str) {
+    let line = line.trim();
+    if let Some((id, path)) = line.split_once(' ')
+        && !id.is_empty()
+        && id.chars().all(|c| c.is_ascii_digit())
+        && let Ok(id) = id.parse::<usize>()
+        && id < len
+    {
+        return (Some(id), path.trim());
+    }
+    (None, line)
+}
+
+/// Works out the changes between the original entries and the edited lines.
+pub fn diff(entries: &[(PathBuf, bool)], lines: &[String]) -> Vec<Change> {
+    let mut seen = vec![false; entries.len()];
+    let mut ret = vec![];
+    for line in lines {
+        let (id, path) = parse_line(line, entries.len());
+        if path.is_empty() {
+            continue;
+        }
+        let target = PathBuf::from(path.trim_end_matches('/'));
+        match id {
+            Some(id) => {
+                let from = &entries[id].0;
+                if seen[id] {
+                    ret.push(Change::Copy(from.clone(), target));
+                } else {
+                    seen[id] = true;
+                    if *from != target {
+                        ret.push(Change::Move(from.clone(), target));
+                    }
+                }
+            }
+            None => ret.push(Change::Create(target, path.ends_with('/'))),
+        }
+    }
+    // A line that lost its id but kept its path keeps its entry, instead of
+    // deleting it and creating an empty one in its place.
+    ret.retain(|c| match c {
+        Change::Create(path, _) => match entries.iter().position(|(p, _)| p == path) {
+            Some(id) if !seen[id] => {
+                seen[id] = true;
+                false
+            }
+            _ => true,
+        },
+        _ => true,
+    });
+    for (id, (path, _)) in entries.iter().enumerate() {
+        if !seen[id] {
+            ret.push(Change::Delete(path.clone()));
+        }
+    }
+    ret
+}
+
+/// Renders changes as popup lines.
+pub fn preview(changes: &[Change]) -> Vec<Vec<StyledChar>> {
+    const MAX: usize = 16;
+    let mut ret: Vec<Vec<StyledChar>> = changes
+        .iter()
+        .take(MAX)
+        .map(|c| StyledChar::colour_string(&format!("  {c}"), c.colour()))
+        .collect();
+    if changes.len() > MAX {
+        ret.push(StyledChar::colour_string(
+            &format!("  ... and {} more", changes.len() - MAX),
+            "\x1b[2m",
+        ));
+    }
+    ret
+}
+
+/// A path in the working directory that nothing has yet, to stage an entry at.
+fn unused_path(kind: &str) -> PathBuf {
+    let mut i = 0;
+    loop {
+        let path = PathBuf::from(format!(".bim-{kind}-{i}"));
+        if fs::symlink_metadata(&path).is_err() {
+            return path;
+        }
+        i += 1;
+    }
+}
+
+fn depth(path: &Path) -> usize {
+    path.components().count()
+}
+
+fn exists(path: &Path) -> io::Result<()> {
+    if path.exists() {
+        Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", path.display()),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+impl Buffer {
+    /// Starts editing the listing, or shows the changes made to it for confirmation.
+    pub fn direx_edit(&mut self) {
+        let Some(entries) = &self.direx.editing else {
+            let entries: Vec<(PathBuf, bool)> = self
+                .direx
+                .entries
+                .iter()
+                .map(|e| (e.path.clone(), e.is_dir))
+                .collect();
+            self.contents = lines(&entries);
+            self.direx.editing = Some(entries);
+            self.cursor_pos.idx = 0;
+            self.update_highlighting();
+            self.alert = Alert::new(
+                &["Editing direx, Ctrl-w to apply the edits".to_string()],
+                2_000_000,
+            );
+            return;
+        };
+        let changes = diff(entries, &self.contents);
+        if changes.is_empty() {
+            self.direx.editing = None;
+            self.direx_refresh();
+            return;
+        }
+        self.direx.pending = Some(super::Op::Apply(changes));
+        self.mode = Mode::Confirm;
+    }
+
+    /// Performs the changes of an edited listing, or keeps editing if not `confirmed`.
+    /// Failed changes are reported without stopping the others.
+    pub fn direx_apply(&mut self, changes: Vec<Change>, confirmed: bool) {
+        if !confirmed {
+            self.alert = Alert::new(&["Cancelled, still editing".to_string()], 1_000_000);
+            return;
+        }
+        let mut errors = vec![];
+        let mut moves = vec![];
+        let mut copies = vec![];
+        let mut creates = vec![];
+        let mut deletes = vec![];
+        for c in changes {
+            match c {
+                Change::Move(from, to) => moves.push((from, to)),
+                Change::Copy(from, to) => copies.push((from, to)),
+                Change::Create(path, is_dir) => creates.push((path, is_dir)),
+                Change::Delete(path) => deletes.push(path),
+            }
+        }
+        let total = moves.len() + copies.len() + creates.len() + deletes.len();
+
+        // Copies are made from the original paths, before anything moves.
+        let mut staged_copies = vec![];
+        for (from, to) in copies {
+            let tmp = unused_path("copy");
+            match copy_rec(&from, &tmp) {
+                Ok(()) => staged_copies.push((tmp, from, to)),
+                Err(e) => errors.push(format!("copy {}: {e}", from.display())),
+            }
+        }
+        // Moves go through temporary names so that entries can swap places,
+        // deepest first so that entries inside moved directories are still found.
+        moves.sort_by_key(|(from, _)| std::cmp::Reverse(depth(from)));
+        let mut staged_moves = vec![];
+        for (from, to) in moves {
+            let tmp = unused_path("move");
+            match fs::rename(&from, &tmp) {
+                Ok(()) => staged_moves.push((tmp, from, to)),
+                Err(e) => errors.push(format!("move {}: {e}", from.display())),
+            }
+        }
+        deletes.sort_by_key(|p| std::cmp::Reverse(depth(p)));
+        for path in deletes {
+            // Entries in moved directories are staged along with them.
+            let staged = staged_moves
+                .iter()
+                .filter_map(|(tmp, from, _)| {
+                    Some((depth(from), tmp.join(path.strip_prefix(from).ok()?)))
+                })
+                .max_by_key(|(d, _)| *d)
+                .map_or(path.clone(), |(_, p)| p);
+            let res = if staged.is_dir() {
+                fs::remove_dir_all(&staged)
+            } else {
+                fs::remove_file(&staged)
+            };
+            match res {
+                Ok(()) => {
+                    self.buffer_history.remove(&path);
+                    self.direx.expanded.retain(|p| !p.starts_with(&path));
+                }
+                Err(e) => errors.push(format!("delete {}: {e}", path.display())),
+            }
+        }
+        // Shallowest first so that entries moved into moved directories have a place to go.
+        staged_moves.sort_by_key(|(_, _, to)| depth(to));
+        // Renames done so far, to follow entries of renamed directories.
+        let mut renamed: Vec<(PathBuf, PathBuf)> = vec![];
+        for (tmp, from, to) in staged_moves {
+            let res = exists(&to)
+                .and_then(|_| create_parent(&to))
+                .and_then(|_| fs::rename(&tmp, &to));
+            match res {
+                Ok(()) => {
+                    let mut old = from.clone();
+                    for (f, t) in &renamed {
+                        if let Ok(rest) = old.strip_prefix(f) {
+                            old = t.join(rest);
+                        }
+                    }
+                    self.buffer_history.rename(&old, &to);
+                    self.direx.rename_expanded(&old, &to);
+                    renamed.push((old, to));
+                }
+                Err(e) => {
+                    // Put it back where it was, if possible.
+                    _ = fs::rename(&tmp, &from);
+                    errors.push(format!("move {}: {e}", from.display()));
+                }
+            }
+        }
+        for (tmp, from, to) in staged_copies {
+            let res = exists(&to)
+                .and_then(|_| create_parent(&to))
+                .and_then(|_| fs::rename(&tmp, &to));
+            if let Err(e) = res {
+                if tmp.is_dir() {
+                    _ = fs::remove_dir_all(&tmp);
+                } else {
+                    _ = fs::remove_file(&tmp);
+                }
+                errors.push(format!("copy {}: {e}", from.display()));
+            }
+        }
+        for (path, is_dir) in creates {
+            let res = exists(&path).and_then(|_| {
+                if is_dir {
+                    fs::create_dir_all(&path)
+                } else {
+                    create_parent(&path).and_then(|_| fs::File::create(&path).map(|_| ()))
+                }
+            });
+            if let Err(e) = res {
+                errors.push(format!("create {}: {e}", path.display()));
+            }
+        }
+
+        self.direx.editing = None;
+        self.direx_refresh();
+        let mut msg = vec![format!(
+            "Applied {} of {total} changes",
+            total - errors.len()
+        )];
+        let timeout = if errors.is_empty() {
+            1_000_000
+        } else {
+            5_000_000
+        };
+        msg.extend(errors);
+        self.alert = Alert::new(&msg, timeout);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<(PathBuf, bool)> {
+        vec![
+            (PathBuf::from("src"), true),
+            (PathBuf::from("src/main.rs"), false),
+        ]
+    }
+
+    fn shown(changes: &[Change]) -> Vec<String> {
+        changes.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn unchanged() {
+        let entries = entries();
+        assert!(diff(&entries, &lines(&entries)).is_empty());
+    }
+
+    #[test]
+    fn id_removed_keeps_entry() {
+        let lines = ["0 src/".to_string(), "src/main.rs".to_string()];
+        assert!(diff(&entries(), &lines).is_empty());
+    }
+
+    #[test]
+    fn moved_and_created_in_its_place() {
+        let lines = [
+            "0 src/".to_string(),
+            "1 src/lib.rs".to_string(),
+            "src/main.rs".to_string(),
+        ];
+        assert_eq!(
+            shown(&diff(&entries(), &lines)),
+            ["move   src/main.rs -> src/lib.rs", "create src/main.rs"]
+        );
+    }
+
+    #[test]
+    fn removed_and_copied() {
+        let lines = [
+            "0 src/".to_string(),
+            "0 lib/".to_string(),
+            "new.rs".to_string(),
+        ];
+        assert_eq!(
+            shown(&diff(&entries(), &lines)),
+            ["copy   src -> lib", "create new.rs", "delete src/main.rs"]
+        );
+    }
+}
@@ -0,0 +1,476 @@
+//! Directory explorer.
+//!
+//! `*direx` shows the working directory as a tree. Directories are only read
+//! once they are expanded. Files can be created, moved, copied and deleted
+//! from it, or in bulk by editing the paths as text (see [`edit`]).
+
+pub mod edit;
+
+use crate::buffer::{Alert, Buffer, Cursor, Mode};
+use crate::ignore::Ignore;
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+fn get_dirs_rec(dir: &Path, ignore: &Ignore, ret: &mut Vec<String>) {
+    for (path, is_dir) in read_dir(dir, ignore) {
+        if is_dir {
+            get_dirs_rec(&path, &ignore.enter(&path), ret);
+        } else {
+            ret.push(path.display().to_string());
+        }
+    }
+}
+
+/// Lists every file below the working directory that isn't ignored.
+pub fn get_dirs() -> Vec<String> {
+    let mut ret = vec![];
+    get_dirs_rec(Path::new(""), &Ignore::root(), &mut ret);
+    ret
+}
+
+/// Reads the entries of a directory that aren't ignored, directories first.
+/// `dir` is empty for the working directory.
+fn read_dir(dir: &Path, ignore: &Ignore) -> Vec<(PathBuf, bool)> {
+    let read = if dir.as_os_str().is_empty() {
+        fs::read_dir(".")
+    } else {
+        fs::read_dir(dir)
+    };
+    let Ok(paths) = read else {
+        return vec![];
+    };
+    let mut ret: Vec<(PathBuf, bool)> = paths
+        .flatten()
+        .map(|p| {
+            let is_dir = p.path().is_dir();
+            (dir.join(p.file_name()), is_dir)
+        })
+        .filter(|(p, is_dir)| !ignore.is_ignored(p, *is_dir))
+        .collect();
+    ret.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    ret
+}
+
+/// Normalises a path so it can be compared with entry paths.
+pub fn normalise(path: &str) -> PathBuf {
+    let path = Path::new(path);
+    path.strip_prefix(".").unwrap_or(path).to_path_buf()
+}
+
+/// Canonical form of a path that may not exist yet.
+fn canonical(path: &Path) -> io::Result<PathBuf> {
+    if path.as_os_str().is_empty() {
+        return fs::canonicalize(".");
+    }
+    match (fs::canonicalize(path), path.parent(), path.file_name()) {
+        (Ok(p), _, _) => Ok(p),
+        (Err(_), Some(parent), Some(name)) => Ok(canonical(parent)?.join(name)),
+        (Err(e), _, _) => Err(e),
+    }
+}
+
+/// Copies a file, or a directory with everything in it. Symlinks are copied
+/// as links rather than followed.
+fn copy_rec(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(from)?.is_dir() && canonical(to)?.starts_with(canonical(from)?) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("can't copy {} into itself", from.display()),
+        ));
+    }
+    copy_tree(from, to)
+}
+
+fn copy_tree(from: &Path, to: &Path) -> io::Result<()> {
+    let kind = fs::symlink_metadata(from)?.file_type();
+    if kind.is_symlink() {
+        copy_link(from, to)
+    } else if kind.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_tree(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        fs::copy(from, to).map(|_| ())
+    }
+}
+
+#[cfg(unix)]
+fn copy_link(from: &Path, to: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(from)?, to)
+}
+
+#[cfg(windows)]
+fn copy_link(from: &Path, to: &Path) -> io::Result<()> {
+    let target = fs::read_link(from)?;
+    if from.is_dir() {
+        std::os::windows::fs::symlink_dir(target, to)
+    } else {
+        std::os::windows::fs::symlink_file(target, to)
+    }
+}
+
+/// Creates the directories above `path`.
+fn create_parent(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => fs::create_dir_all(p),
+        _ => Ok(()),
+    }
+}
+
+/// Moving or copying into an existing directory keeps the name.
+fn into_dir(from: &Path, to: &Path) -> PathBuf {
+    match from.file_name() {
+        Some(name) if to.is_dir() => to.join(name),
+        _ => to.to_path_buf(),
+    }
+}
+
+/// File operation waiting for a path or a confirmation.
+pub enum Op {
+    /// Create a file, or a directory if the path ends with `/`.
+    Create,
+    /// Move the path to another.
+    Move(PathBuf),
+    /// Copy the path to another.
+    Copy(PathBuf),
+    /// Delete the path.
+    Delete(PathBuf),
+    /// Apply the changes of an edited listing.
+    Apply(Vec<edit::Change>),
+}
+
+/// A line of the tree.
+pub struct Entry {
+    pub path: PathBuf,
+    pub depth: usize,
+    pub is_dir: bool,
+}
+
+/// State of the directory tree.
+pub struct Direx {
+    /// Directories that are expanded.
+    pub expanded: HashSet<PathBuf>,
+    /// Entries shown, in the order of the lines.
+    pub entries: Vec<Entry>,
+    /// Operation being prompted for.
+    pub pending: Option<Op>,
+    /// Paths and whether they are directories, indexed by their id in the listing,
+    /// while the listing is being edited.
+    pub editing: Option<Vec<(PathBuf, bool)>>,
+}
+
+impl Direx {
+    pub fn new() -> Self {
+        Direx {
+            expanded: HashSet::new(),
+            entries: vec![],
+            pending: None,
+            editing: None,
+        }
+    }
+
+    fn push_dir(&mut self, dir: &Path, ignore: &Ignore, depth: usize) {
+        for (path, is_dir) in read_dir(dir, ignore) {
+            let expanded = is_dir && self.expanded.contains(&path);
+            self.entries.push(Entry {
+                path: path.clone(),
+                depth,
+                is_dir,
+            });
+            if expanded {
+                self.push_dir(&path, &ignore.enter(&path), depth + 1);
+            }
+        }
+    }
+
+    /// Reads the tree again, descending into expanded directories only.
+    pub fn refresh(&mut self) {
+        self.entries.clear();
+        self.push_dir(Path::new(""), &Ignore::root(), 0);
+    }
+
+    /// Renders the entries as lines.
+    pub fn lines(&self) -> Vec<String> {
+        let mut ret: Vec<String> = self
+            .entries
+            .iter()
+            .map(|e| {
+                let name = e
+                    .path
+                    .file_name()
+                    .map_or(String::new(), |n| n.to_string_lossy().to_string());
+                let indent = "  ".repeat(e.depth);
+                if !e.is_dir {
+                    format!("{indent}  {name}")
+                } else if self.expanded.contains(&e.path) {
+                    format!("{indent}▾ {name}/")
+                } else {
+                    format!("{indent}▸ {name}/")
+                }
+            })
+            .collect();
+        if ret.is_empty() {
+            ret.push(String::new());
+        }
+        ret
+    }
+
+    /// Expands or collapses the directory at line `idx`.
+    /// Return value signifies whether the line is a directory.
+    pub fn toggle(&mut self, idx: usize) -> bool {
+        match self.entries.get(idx) {
+            Some(e) if e.is_dir => {
+                if !self.expanded.remove(&e.path) {
+                    self.expanded.insert(e.path.clone());
+                }
+                self.refresh();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Keeps directories under a moved path expanded.
+    pub fn rename_expanded(&mut self, from: &Path, to: &Path) {
+        self.expanded = self
+            .expanded
+            .drain()
+            .map(|p| match p.strip_prefix(from) {
+                Ok(rest) => to.join(rest),
+                Err(_) => p,
+            })
+            .collect();
+    }
+
+    /// Expands the directories containing `path` and returns its line, if it is shown.
+    pub fn reveal(&mut self, path: &str) -> Option<usize> {
+        let path = normalise(path);
+        for ancestor in path.ancestors().skip(1) {
+            if !ancestor.as_os_str().is_empty() {
+                self.expanded.insert(ancestor.to_path_buf());
+            }
+        }
+        self.refresh();
+        self.entries.iter().position(|e| e.path == path)
+    }
+}
+
+impl Buffer {
+    /// Shows the directory tree again, keeping the cursor on the same path.
+    pub fn direx_refresh(&mut self) {
+        let current = self
+            .direx
+            .entries
+            .get(self.cursor_pos.line)
+            .map(|e| e.path.clone());
+        self.direx.refresh();
+        self.contents = self.direx.lines();
+        if let Some(line) =
+            current.and_then(|c| self.direx.entries.iter().position(|e| e.path == c))
+        {
+            self.cursor_pos.line = line;
+        }
+        if self.cursor_pos.line >= self.contents.len() {
+            self.cursor_pos.line = self.contents.len() - 1;
+        }
+        self.cursor_pos.idx = 0;
+        self.update_highlighting();
+    }
+
+    /// Moves the cursor in `*direx` to `path`, expanding the directories above it.
+    pub fn direx_reveal(&mut self, path: &str) {
+        let line = self.direx.reveal(path);
+        self.contents = self.direx.lines();
+        if let Some(line) = line {
+            self.cursor_pos = Cursor { line, idx: 0 };
+        }
+        self.update_highlighting();
+    }
+
+    /// Expands or collapses the directory under the cursor, or opens the file under it.
+    /// Return value signifies whether a file was opened.
+    pub fn direx_select(&mut self) -> bool {
+        if self.direx.editing.is_some() {
+            return false;
+        }
+        if self.direx.toggle(self.cursor_pos.line) {
+            self.contents = self.direx.lines();
+            self.cursor_pos.idx = 0;
+            self.update_highlighting();
+            return false;
+        }
+        let Some(entry) = self.direx.entries.get(self.cursor_pos.line) else {
+            return false;
+        };
+        let newpath = entry.path.display().to_string();
+        if newpath.ends_with(".exe") {
+            self.alert = Alert::new(&[String::from("You shouldn't do that")], 1_000_000);
+            return false;
+        }
+        self.open_file(&newpath);
+        true
+    }
+
+    /// Handles the file operation keys: Ctrl with `a` to create, `v` to move,
+    /// `c` to copy, `x` to delete and `w` to edit the listing.
+    pub fn direx_ctrl(&mut self, c: char) {
+        if self.direx.editing.is_some() && c != 'w' {
+            self.alert = Alert::new(
+                &["Apply the edits with Ctrl-w first".to_string()],
+                1_000_000,
+            );
+            return;
+        }
+        match c {
+            'w' => self.direx_edit(),
+            'a' => self.direx_begin(Op::Create),
+            'v' => self.direx_begin(Op::Move(PathBuf::new())),
+            'c' => self.direx_begin(Op::Copy(PathBuf::new())),
+            'x' => self.direx_begin(Op::Delete(PathBuf::new())),
+            _ => {}
+        }
+    }
+
+    /// Starts a file operation on the entry under the cursor,
+    /// prompting for a path or a confirmation.
+    pub fn direx_begin(&mut self, op: Op) {
+        let Some(entry) = self.direx.entries.get(self.cursor_pos.line) else {
+            if let Op::Create = op {
+                self.direx.pending = Some(op);
+                self.temp_str.clear();
+                self.mode = Mode::DirexPath;
+            }
+            return;
+        };
+        let path = entry.path.clone();
+        let op = match op {
+            Op::Create => Op::Create,
+            Op::Move(_) => Op::Move(path.clone()),
+            Op::Copy(_) => Op::Copy(path.clone()),
+            Op::Delete(_) => Op::Delete(path.clone()),
+            Op::Apply(_) => return,
+        };
+        let display = path.display().to_string();
+        let (msg, prefill) = match op {
+            Op::Create => {
+                let dir = if entry.is_dir && self.direx.expanded.contains(&path) {
+                    Some(path.as_path())
+                } else {
+                    path.parent()
+                };
+                let prefill = match dir {
+                    Some(d) if !d.as_os_str().is_empty() => format!("{}/", d.display()),
+                    _ => String::new(),
+                };
+                ("Create (end with / for a directory)".to_string(), prefill)
+            }
+            Op::Move(_) => (format!("Move {display} to"), display),
+            Op::Copy(_) => (format!("Copy {display} to"), display),
+            Op::Delete(_) => (format!("Delete {display}? (y/n)"), String::new()),
+            Op::Apply(_) => return,
+        };
+        self.mode = if let Op::Delete(_) = op {
+            Mode::Confirm
+        } else {
+            Mode::DirexPath
+        };
+        self.direx.pending = Some(op);
+        self.temp_str = prefill;
+        self.alert = Alert::new(&[msg], 3_000_000);
+    }
+
+    /// Performs the pending operation with the path in `temp_str`.
+    pub fn direx_finish(&mut self) {
+        let input = std::mem::take(&mut self.temp_str);
+        let target = PathBuf::from(input.trim());
+        let Some(op) = self.direx.pending.take() else {
+            return;
+        };
+        if target.as_os_str().is_empty() {
+            return;
+        }
+        let exists = |p: &Path| {
+            if p.exists() {
+                Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} already exists", p.display()),
+                ))
+            } else {
+                Ok(())
+            }
+        };
+        let res = match op {
+            Op::Create => exists(&target).and_then(|_| {
+                if input.trim().ends_with('/') {
+                    fs::create_dir_all(&target)?;
+                } else {
+                    create_parent(&target)?;
+                    fs::File::create(&target)?;
+                }
+                Ok(target)
+            }),
+            Op::Move(from) => {
+                let to = into_dir(&from, &target);
+                exists(&to).and_then(|_| {
+                    create_parent(&to)?;
+                    fs::rename(&from, &to)?;
+                    self.buffer_history.rename(&from, &to);
+                    self.direx.rename_expanded(&from, &to);
+                    Ok(to)
+                })
+            }
+            Op::Copy(from) => {
+                let to = into_dir(&from, &target);
+                exists(&to).and_then(|_| {
+                    create_parent(&to)?;
+                    copy_rec(&from, &to)?;
+                    Ok(to)
+                })
+            }
+            Op::Delete(_) | Op::Apply(_) => return,
+        };
+        match res {
+            Ok(path) => self.direx_reveal(&path.display().to_string()),
+            Err(e) => {
+                self.alert = Alert::new(&[e.to_string()], 3_000_000);
+                self.direx_refresh();
+            }
+        }
+    }
+
+    /// Answers the confirmation of a pending deletion or of edits.
+    pub fn direx_confirm(&mut self, yes: bool) {
+        let path = match self.direx.pending.take() {
+            Some(Op::Delete(path)) => path,
+            Some(Op::Apply(changes)) => {
+                self.direx_apply(changes, yes);
+                return;
+            }
+            _ => return,
+        };
+        if !yes {
+            self.alert = Alert::new(&["Cancelled".to_string()], 1_000_000);
+            return;
+        }
+        let res = if path.is_dir() {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        match res {
+            Ok(()) => {
+                self.buffer_history.remove(&path);
+                self.direx.expanded.retain(|p| !p.starts_with(&path));
+                self.alert = Alert::new(&[format!("Deleted {}", path.display())], 1_000_000);
+            }
+            Err(e) => self.alert = Alert::new(&[e.to_string()], 3_000_000),
+        }
+        self.direx_refresh();
+    }
+}
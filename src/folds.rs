@@ -0,0 +1,225 @@
+//! Code folding.
+//!
+//! A closed fold hides the lines of a region behind its first line, which is
+//! shown with the number of hidden lines. Regions are found by indentation, by
+//! bracket pairs or by `{{{` and `}}}` markers, as chosen with the
+//! `fold-method` variable (`indent`, `brackets` or `marker`).
+//!
+//! Moving up and down skips closed folds, and folds the cursor ends up in, after
+//! goto or a search for example, are opened.
+
+use crate::buffer::{Alert, BimVar, Buffer, Cursor};
+use crate::is_close_bracket;
+use std::collections::HashMap;
+
+/// Most lines searched above the cursor for the region around it.
+const SEARCH_LINES: usize = 1000;
+
+#[derive(Default)]
+pub struct Folds {
+    /// Closed folds by file, as the first and last line of their region.
+    pub closed: HashMap<String, Vec<(usize, usize)>>,
+}
+
+#[derive(Clone, Copy)]
+enum Method {
+    Indent,
+    Brackets,
+    Marker,
+}
+
+/// Summary shown after the first line of a closed fold.
+pub fn summary(hidden: usize) -> String {
+    format!(" ... {hidden} lines")
+}
+
+impl Buffer {
+    fn fold_method(&self) -> Method {
+        match self.vars.get("fold-method") {
+            Some(BimVar::Str(s)) if s == "brackets" => Method::Brackets,
+            Some(BimVar::Str(s)) if s == "marker" => Method::Marker,
+            _ => Method::Indent,
+        }
+    }
+
+    fn closed_folds(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.folds
+            .closed
+            .get(&self.filepath)
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(|(_, end)| *end < self.contents.len())
+    }
+
+    /// Last line of the region starting at `line`, if it has one.
+    fn region(&self, line: usize, method: Method) -> Option<usize> {
+        match method {
+            Method::Indent => {
+                let size = self.lang.indent_size().max(1);
+                let level = |l: &str| (l.len() - l.trim_start().len()) / size;
+                let first = &self.contents[line];
+                if first.trim().is_empty() {
+                    return None;
+                }
+                self.contents
+                    .iter()
+                    .enumerate()
+                    .skip(line + 1)
+                    .filter(|(_, l)| !l.trim().is_empty())
+                    .take_while(|(_, l)| level(l) > level(first))
+                    .last()
+                    .map(|(i, _)| i)
+            }
+            Method::Brackets => (0..self.highlighted_contents[line].len()).find_map(|idx| {
+                let open = Cursor { line, idx };
+                if self.bracket_at(open).is_none_or(is_close_bracket) {
+                    return None;
+                }
+                let close = self.bracket_pair(open)?;
+                // The closing line stays shown if it goes on, like `} else {`.
+                let closes = self.contents[close.line]
+                    .trim()
+                    .chars()
+                    .all(|c| is_close_bracket(c) || matches!(c, ';' | ','));
+                let end = if closes {
+                    close.line
+                } else {
+                    close.line.saturating_sub(1)
+                };
+                (end > line).then_some(end)
+            }),
+            Method::Marker => {
+                if !self.contents[line].contains("{{{") {
+                    return None;
+                }
+                let mut depth = 0;
+                for (i, l) in self.contents.iter().enumerate().skip(line) {
+                    depth += l.matches("{{{").count() as isize;
+                    depth -= l.matches("}}}").count() as isize;
+                    if depth <= 0 {
+                        return (i > line).then_some(i);
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    /// Closes the fold of the region from `start` to `end`, replacing folds it overlaps.
+    fn close_fold(&mut self, start: usize, end: usize) {
+        let folds = self.folds.closed.entry(self.filepath.clone()).or_default();
+        folds.retain(|&(s, e)| {
+            (s, e) != (start, end)
+                && (e < start || s > end || (start <= s && e <= end) || (s <= start && end <= e))
+        });
+        folds.push((start, end));
+    }
+
+    /// Last line of the outermost closed fold starting at `line`.
+    pub fn fold_end(&self, line: usize) -> Option<usize> {
+        self.closed_folds()
+            .filter(|(start, _)| *start == line)
+            .map(|(_, end)| end)
+            .max()
+    }
+
+    /// The line shown in place of `line`, which is the first line of the
+    /// outermost closed fold hiding it.
+    pub fn shown_line(&self, line: usize) -> usize {
+        self.closed_folds()
+            .filter(|(start, end)| *start < line && line <= *end)
+            .map(|(start, _)| start)
+            .min()
+            .unwrap_or(line)
+    }
+
+    /// The shown line after `line`.
+    pub fn next_shown(&self, line: usize) -> usize {
+        self.fold_end(line).unwrap_or(line) + 1
+    }
+
+    /// The shown line `n` shown lines above `line`, or the first line.
+    pub fn shown_above(&self, mut line: usize, n: usize) -> usize {
+        for _ in 0..n {
+            if line == 0 {
+                break;
+            }
+            line = self.shown_line(line - 1);
+        }
+        line
+    }
+
+    /// Number of shown lines from one shown line to another.
+    pub fn shown_between(&self, from: usize, to: usize) -> usize {
+        let (mut line, to) = (from.min(to), from.max(to));
+        let mut ret = 0;
+        while line < to {
+            line = self.next_shown(line);
+            ret += 1;
+        }
+        ret
+    }
+
+    /// Opens the fold on the cursor line, or closes the region around the cursor.
+    pub fn toggle_fold(&mut self) {
+        let line = self.cursor_pos.line;
+        if let Some(end) = self.fold_end(line) {
+            if let Some(folds) = self.folds.closed.get_mut(&self.filepath) {
+                folds.retain(|f| *f != (line, end));
+            }
+            return;
+        }
+        let method = self.fold_method();
+        let around = (line.saturating_sub(SEARCH_LINES)..=line)
+            .rev()
+            .find_map(|l| Some((l, self.region(l, method).filter(|e| *e >= line)?)));
+        match around {
+            Some((start, end)) => {
+                self.close_fold(start, end);
+                self.cursor_pos.line = start;
+                self.clamp_cursor();
+            }
+            None => self.alert = Alert::new(&["Nothing to fold".to_string()], 1_000_000),
+        }
+    }
+
+    /// Opens every fold, or closes every region if none are closed.
+    pub fn toggle_all_folds(&mut self) {
+        if self.closed_folds().next().is_some() {
+            self.folds.closed.remove(&self.filepath);
+            return;
+        }
+        let method = self.fold_method();
+        for line in 0..self.contents.len() {
+            if let Some(end) = self.region(line, method) {
+                self.close_fold(line, end);
+            }
+        }
+        if self.closed_folds().next().is_none() {
+            self.alert = Alert::new(&["Nothing to fold".to_string()], 1_000_000);
+        }
+        self.cursor_pos.line = self.shown_line(self.cursor_pos.line);
+        self.clamp_cursor();
+    }
+
+    /// Opens the folds hiding the cursor.
+    pub fn reveal_cursor(&mut self) {
+        let line = self.cursor_pos.line;
+        if let Some(folds) = self.folds.closed.get_mut(&self.filepath) {
+            folds.retain(|(start, end)| !(*start < line && line <= *end));
+        }
+    }
+
+    /// Moves the folds of the current file after lines `start..old_end` were
+    /// replaced by `start..new_end`. Folds with replaced lines are opened.
+    pub fn shift_folds(&mut self, start: usize, old_end: usize, new_end: usize) {
+        if let Some(folds) = self.folds.closed.get_mut(&self.filepath) {
+            folds.retain(|(s, e)| *e < start || *s >= old_end);
+            for (s, e) in folds.iter_mut().filter(|(s, _)| *s >= old_end) {
+                *s = *s - old_end + new_end;
+                *e = *e - old_end + new_end;
+            }
+        }
+    }
+}
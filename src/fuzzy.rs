@@ -0,0 +1,154 @@
+//! Fuzzy matching and the picker list shown above the bottom bar.
+
+use crate::languages::StyledChar;
+
+const MATCH_SCORE: isize = 16;
+const CONSECUTIVE_BONUS: isize = 12;
+const PREFIX_BONUS: isize = 10;
+const BOUNDARY_BONUS: isize = 8;
+const GAP_PENALTY: isize = 1;
+
+fn is_boundary(prev: char, c: char) -> bool {
+    matches!(prev, '/' | '\\' | '_' | '-' | '.' | ' ' | ':')
+        || (prev.is_lowercase() && c.is_uppercase())
+}
+
+/// Scores how well `query` fuzzy matches `candidate`. Higher is better.
+/// Returns the score and the char indices of `candidate` that were matched,
+/// or `None` if `query` is not a subsequence of `candidate`.
+/// Matching ignores case unless `query` contains uppercase letters.
+pub fn score(query: &str, candidate: &str) -> Option<(isize, Vec<usize>)> {
+    let smart_case = query.chars().any(|c| c.is_uppercase());
+    let eq = |a: char, b: char| {
+        if smart_case {
+            a == b
+        } else {
+            a.to_lowercase().eq(b.to_lowercase())
+        }
+    };
+    let q: Vec<char> = query.chars().collect();
+    let c: Vec<char> = candidate.chars().collect();
+    if q.is_empty() {
+        return Some((0, vec![]));
+    }
+    if q.len() > c.len() {
+        return None;
+    }
+    let bonus: Vec<isize> = (0..c.len())
+        .map(|j| match j {
+            0 => PREFIX_BONUS,
+            _ if is_boundary(c[j - 1], c[j]) => BOUNDARY_BONUS,
+            _ => 0,
+        })
+        .collect();
+    // best[i][j] is the best score of matching q[..=i] with q[i] matched at c[j].
+    let mut best = vec![vec![None; c.len()]; q.len()];
+    let mut from = vec![vec![0; c.len()]; q.len()];
+    for i in 0..q.len() {
+        // Best of best[i - 1][k] + GAP_PENALTY * k for k < j - 1, and its k.
+        let mut running: Option<(isize, usize)> = None;
+        for j in i..c.len() {
+            if i > 0
+                && j >= 2
+                && let Some(s) = best[i - 1][j - 2]
+            {
+                let s = s + GAP_PENALTY * (j - 2) as isize;
+                if running.is_none_or(|(r, _)| s > r) {
+                    running = Some((s, j - 2));
+                }
+            }
+            if !eq(q[i], c[j]) {
+                continue;
+            }
+            let here = MATCH_SCORE + bonus[j];
+            if i == 0 {
+                best[i][j] = Some(here - GAP_PENALTY * j as isize / 4);
+                continue;
+            }
+            let consecutive = best[i - 1][j - 1].map(|s| (s + here + CONSECUTIVE_BONUS, j - 1));
+            let gapped = running.map(|(s, k)| (s - GAP_PENALTY * (j - 1) as isize + here, k));
+            let pick = match (consecutive, gapped) {
+                (Some(a), Some(b)) => Some(if a.0 >= b.0 { a } else { b }),
+                (a, b) => a.or(b),
+            };
+            if let Some((s, k)) = pick {
+                best[i][j] = Some(s);
+                from[i][j] = k;
+            }
+        }
+    }
+    let last = q.len() - 1;
+    let (mut j, total) = (0..c.len())
+        .filter_map(|j| best[last][j].map(|s| (j, s)))
+        .max_by_key(|(_, s)| *s)?;
+    let mut matched = vec![j];
+    for i in (1..q.len()).rev() {
+        j = from[i][j];
+        matched.push(j);
+    }
+    matched.reverse();
+    Some((total - (c.len() as isize - q.len() as isize) / 8, matched))
+}
+
+/// An item in a picker list.
+pub struct Candidate {
+    /// Text that is matched against.
+    pub text: String,
+    /// Extra information shown next to the text.
+    pub detail: String,
+    /// Char indices of `text` that matched the query.
+    pub matched: Vec<usize>,
+    pub score: isize,
+}
+
+/// Ranks items of (text, detail) against `query`, dropping those that don't match.
+pub fn rank(query: &str, items: impl Iterator<Item = (String, String)>) -> Vec<Candidate> {
+    let mut ret: Vec<Candidate> = items
+        .filter_map(|(text, detail)| {
+            score(query, &text).map(|(score, matched)| Candidate {
+                text,
+                detail,
+                matched,
+                score,
+            })
+        })
+        .collect();
+    ret.sort_by_key(|c| -c.score);
+    ret
+}
+
+/// Renders at most `max` candidates around the `selected` one as popup lines.
+/// Matched chars are highlighted.
+pub fn render(candidates: &[Candidate], selected: usize, max: usize) -> Vec<Vec<StyledChar>> {
+    let start = (selected + 1).saturating_sub(max);
+    let text_width = candidates
+        .iter()
+        .skip(start)
+        .take(max)
+        .map(|c| c.text.chars().count())
+        .max()
+        .unwrap_or(0);
+    candidates
+        .iter()
+        .enumerate()
+        .skip(start)
+        .take(max)
+        .map(|(i, c)| {
+            let is_selected = i == selected;
+            let mut line =
+                StyledChar::colour_string(if is_selected { "> " } else { "  " }, "\x1b[36m");
+            for (ci, ch) in c.text.chars().enumerate() {
+                let style = match (c.matched.contains(&ci), is_selected) {
+                    (true, _) => "\x1b[1;33m",
+                    (false, true) => "\x1b[1m",
+                    (false, false) => "",
+                };
+                line.push(StyledChar { style, ch });
+            }
+            let pad = text_width - c.text.chars().count() + 2;
+            line.extend(StyledChar::from_string(&" ".repeat(pad)));
+            line.extend(StyledChar::colour_string(&c.detail, "\x1b[2m"));
+            line
+        })
+        .collect()
+}
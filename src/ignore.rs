@@ -0,0 +1,274 @@
+//! Ignore files with gitignore semantics.
+//!
+//! Rules are read from `.gitignore` and `.ignore` in every directory, and apply
+//! to the paths below it. Later rules override earlier ones, so rules of nested
+//! directories take precedence. Paths are relative to the working directory,
+//! without a leading `./`. The `.git` directory is always ignored.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Names of the ignore files read in every directory, in order of precedence.
+pub const IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
+#[derive(Clone)]
+struct Rule {
+    pattern: Vec<char>,
+    /// Directory of the ignore file the rule is from.
+    base: PathBuf,
+    negate: bool,
+    dir_only: bool,
+    /// Whether the pattern is matched against the whole path below `base`,
+    /// instead of just the name.
+    anchored: bool,
+}
+
+impl Rule {
+    fn parse(line: &str, base: &Path) -> Option<Rule> {
+        let mut line = line.trim_end_matches(['\r', '\n']);
+        if !line.ends_with("\\ ") {
+            line = line.trim_end();
+        }
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let negate = line.starts_with('!');
+        if negate {
+            line = &line[1..];
+        }
+        if line.starts_with("\\!") || line.starts_with("\\#") {
+            line = &line[1..];
+        }
+        let dir_only = line.ends_with('/');
+        let line = line.trim_end_matches('/');
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        if line.is_empty() {
+            return None;
+        }
+        Some(Rule {
+            pattern: line.chars().collect(),
+            base: base.to_path_buf(),
+            negate,
+            dir_only,
+            anchored,
+        })
+    }
+
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let Ok(rel) = path.strip_prefix(&self.base) else {
+            return false;
+        };
+        let text: Vec<char> = if self.anchored {
+            rel.to_string_lossy().replace('\\', "/").chars().collect()
+        } else {
+            match rel.file_name() {
+                Some(name) => name.to_string_lossy().chars().collect(),
+                None => return false,
+            }
+        };
+        glob(&self.pattern, &text)
+    }
+}
+
+/// Matches a `[...]` class at the start of `p` against `c`.
+/// Returns whether it matched and the length of the class, or `None` if it is unterminated.
+fn class(p: &[char], c: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negate = matches!(p.get(i), Some('!' | '^'));
+    if negate {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let lo = match p.get(i)? {
+            ']' if !first => break,
+            '\\' => {
+                i += 1;
+                *p.get(i)?
+            }
+            ch => *ch,
+        };
+        first = false;
+        if p.get(i + 1) == Some(&'-') && p.get(i + 2).is_some_and(|ch| *ch != ']') {
+            let hi = p[i + 2];
+            matched |= lo <= c && c <= hi;
+            i += 3;
+        } else {
+            matched |= lo == c;
+            i += 1;
+        }
+    }
+    Some((matched != negate, i + 1))
+}
+
+/// Matches a glob pattern. `*` and `?` don't match `/`, `**` matches anything,
+/// and `**/` matches any number of directories.
+pub fn glob(p: &[char], s: &[char]) -> bool {
+    let mut failed = vec![false; (p.len() + 1) * (s.len() + 1)];
+    glob_at(p, s, 0, 0, &mut failed)
+}
+
+/// Matches `p[pi..]` against `s[si..]`. Positions that failed are remembered,
+/// so that patterns with many stars don't try them again and again.
+fn glob_at(p: &[char], s: &[char], pi: usize, si: usize, failed: &mut [bool]) -> bool {
+    let key = pi * (s.len() + 1) + si;
+    if failed[key] {
+        return false;
+    }
+    let (pr, sr) = (&p[pi..], &s[si..]);
+    let mut at = |pj, sj| glob_at(p, s, pj, sj, failed);
+    let ret = match pr.first() {
+        None => sr.is_empty(),
+        Some('*') if pr.get(1) == Some(&'*') => {
+            if pr.get(2) == Some(&'/') {
+                (si..=s.len()).any(|i| (i == si || s[i - 1] == '/') && at(pi + 3, i))
+            } else {
+                (si..=s.len()).any(|i| at(pi + 2, i))
+            }
+        }
+        Some('*') => {
+            let end = sr
+                .iter()
+                .position(|c| *c == '/')
+                .map_or(s.len(), |e| si + e);
+            (si..=end).any(|i| at(pi + 1, i))
+        }
+        Some('?') => sr.first().is_some_and(|c| *c != '/') && at(pi + 1, si + 1),
+        Some('[') => match (sr.first(), class(pr, sr.first().copied().unwrap_or('/'))) {
+            (Some(c), Some((matched, len))) => *c != '/' && matched && at(pi + len, si + 1),
+            (Some(c), None) => *c == '[' && at(pi + 1, si + 1),
+            (None, _) => false,
+        },
+        Some('\\') if pr.len() > 1 => sr.first() == Some(&pr[1]) && at(pi + 2, si + 1),
+        Some(c) => sr.first() == Some(c) && at(pi + 1, si + 1),
+    };
+    if !ret {
+        failed[key] = true;
+    }
+    ret
+}
+
+/// Ignore rules that apply in a directory.
+#[derive(Clone, Default)]
+pub struct Ignore {
+    rules: Vec<Rule>,
+}
+
+impl Ignore {
+    /// Rules of the working directory.
+    pub fn root() -> Ignore {
+        Ignore::default().enter(Path::new(""))
+    }
+
+    /// Rules of the subdirectory `dir`, adding those of its ignore files.
+    pub fn enter(&self, dir: &Path) -> Ignore {
+        let mut ret = self.clone();
+        for name in IGNORE_FILES {
+            let file = if dir.as_os_str().is_empty() {
+                PathBuf::from(name)
+            } else {
+                dir.join(name)
+            };
+            if let Ok(contents) = fs::read_to_string(file) {
+                ret.rules
+                    .extend(contents.lines().filter_map(|l| Rule::parse(l, dir)));
+            }
+        }
+        ret
+    }
+
+    /// Whether `path` is ignored. The directories above it are assumed not to be.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if path.file_name().is_some_and(|n| n == ".git") {
+            return true;
+        }
+        self.rules
+            .iter()
+            .rev()
+            .find(|r| r.matches(path, is_dir))
+            .is_some_and(|r| !r.negate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn globs(pattern: &str, text: &str) -> bool {
+        let p: Vec<char> = pattern.chars().collect();
+        let s: Vec<char> = text.chars().collect();
+        glob(&p, &s)
+    }
+
+    /// Whether `path` is ignored by the rules in `lines`, read in `base`.
+    fn ignored(lines: &[&str], base: &str, path: &str, is_dir: bool) -> bool {
+        let ignore = Ignore {
+            rules: lines
+                .iter()
+                .filter_map(|l| Rule::parse(l, Path::new(base)))
+                .collect(),
+        };
+        ignore.is_ignored(Path::new(path), is_dir)
+    }
+
+    #[test]
+    fn stars_and_classes() {
+        assert!(globs("*.rs", "main.rs"));
+        assert!(!globs("*.rs", "src/main.rs"));
+        assert!(globs("?a[b-d]", "xac"));
+        assert!(!globs("[!a]", "a"));
+        assert!(globs("\\*", "*"));
+        assert!(!globs("\\*", "a"));
+    }
+
+    #[test]
+    fn double_stars() {
+        assert!(globs("**/foo", "foo"));
+        assert!(globs("**/foo", "a/b/foo"));
+        assert!(!globs("**/foo", "afoo"));
+        assert!(globs("a/**/b", "a/b"));
+        assert!(globs("a/**/b", "a/x/y/b"));
+        assert!(globs("a/**", "a/x/y"));
+    }
+
+    #[test]
+    fn many_stars_are_fast() {
+        let pattern = "*a".repeat(30) + "b";
+        assert!(!globs(&pattern, &"a".repeat(200)));
+        assert!(!globs(&"**a".repeat(30), &"a".repeat(29)));
+    }
+
+    #[test]
+    fn anchoring() {
+        assert!(ignored(&["target"], "", "src/target", true));
+        assert!(ignored(&["/target"], "", "target", true));
+        assert!(!ignored(&["/target"], "", "src/target", true));
+        assert!(ignored(&["doc/*.md"], "", "doc/a.md", false));
+        assert!(!ignored(&["doc/*.md"], "", "x/doc/a.md", false));
+        assert!(ignored(&["/out"], "sub", "sub/out", false));
+        assert!(!ignored(&["/out"], "sub", "out", false));
+    }
+
+    #[test]
+    fn dir_only() {
+        assert!(ignored(&["build/"], "", "build", true));
+        assert!(!ignored(&["build/"], "", "build", false));
+    }
+
+    #[test]
+    fn negation() {
+        let rules = ["*.log", "!keep.log"];
+        assert!(ignored(&rules, "", "a.log", false));
+        assert!(!ignored(&rules, "", "keep.log", false));
+        assert!(ignored(&["!keep.log", "*.log"], "", "keep.log", false));
+        assert!(ignored(&["\\!x"], "", "!x", false));
+        assert!(ignored(&["anything"], "", ".git", true));
+    }
+}
@@ -62,4 +62,7 @@ impl Language for Forest {
     fn display_str(&self) -> &'static str {
         "Forest"
     }
+    fn is_literal(&self, style: &str) -> bool {
+        style == "\x1b[32m"
+    }
 }
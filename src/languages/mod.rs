@@ -12,6 +12,10 @@ pub trait Language {
     fn indent_size(&self) -> usize;
     /// Converts to display string.
     fn display_str(&self) -> &'static str;
+    /// Whether chars highlighted with `style` are in a string or comment.
+    fn is_literal(&self, _style: &str) -> bool {
+        false
+    }
 }
 
 /// Struct for styling chars.
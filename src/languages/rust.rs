@@ -98,4 +98,8 @@ impl Language for Rust {
     fn display_str(&self) -> &'static str {
         "Rust"
     }
+
+    fn is_literal(&self, style: &str) -> bool {
+        matches!(style, "\x1b[2m" | "\x1b[32m" | "\x1b[36m")
+    }
 }
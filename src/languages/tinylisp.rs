@@ -64,4 +64,7 @@ impl Language for Tinylisp {
     fn display_str(&self) -> &'static str {
         "Tinylisp"
     }
+    fn is_literal(&self, style: &str) -> bool {
+        style == "\x1b[32m"
+    }
 }
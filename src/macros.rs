@@ -0,0 +1,317 @@
+//! Keyboard macros.
+//!
+//! `Q` followed by a letter in nav mode starts recording keys into that
+//! register and `Q` stops. `@` followed by the letter plays the keys back, as
+//! many times as the count typed before the `@`. Macros are written as text,
+//! like `ihello<Esc>` with keys such as `<Enter>`, `<C-s>` and `<A-Left>`.
+//! `*macros` lists them one per line as `<register> <keys>`, and `Ctrl-w`
+//! there applies the edits and saves them to the `macros` file of the user
+//! configuration directory. Macros from the `macros` file of the project are
+//! kept apart: they are used for registers the user has no macro in, shown as
+//! comments in `*macros` and never saved.
+
+use crate::buffer::{Alert, Buffer, Mode};
+use crate::config;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::{collections::BTreeMap, fs};
+
+/// Most keys played back for one typed key, so that macros playing themselves stop.
+const PLAYBACK_LIMIT: usize = 100_000;
+
+const NAMES: [(&str, KeyCode); 14] = [
+    ("Esc", KeyCode::Esc),
+    ("Enter", KeyCode::Enter),
+    ("Tab", KeyCode::Tab),
+    ("BackTab", KeyCode::BackTab),
+    ("Backspace", KeyCode::Backspace),
+    ("Delete", KeyCode::Delete),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+];
+
+fn key_name(code: KeyCode) -> Option<String> {
+    match code {
+        KeyCode::Char('<') => Some("lt".to_string()),
+        KeyCode::Char(c) => Some(c.to_string()),
+        KeyCode::F(n) => Some(format!("F{n}")),
+        _ => NAMES
+            .iter()
+            .find(|(_, c)| *c == code)
+            .map(|(n, _)| n.to_string()),
+    }
+}
+
+fn parse_name(name: &str) -> Option<KeyCode> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => return Some(KeyCode::Char(c)),
+        (None, _) => return None,
+        _ => {}
+    }
+    match name {
+        "lt" => Some(KeyCode::Char('<')),
+        "Space" => Some(KeyCode::Char(' ')),
+        _ => match name.strip_prefix('F').and_then(|n| n.parse::<u8>().ok()) {
+            Some(n) => Some(KeyCode::F(n)),
+            None => NAMES.iter().find(|(n, _)| *n == name).map(|(_, c)| *c),
+        },
+    }
+}
+
+/// Writes keys as text. Keys that can't be written are left out.
+pub fn to_text(keys: &[KeyEvent]) -> String {
+    let mut ret = String::new();
+    for key in keys {
+        let Some(name) = key_name(key.code) else {
+            continue;
+        };
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+        if ctrl || alt || name.chars().count() > 1 {
+            ret.push('<');
+            if ctrl {
+                ret.push_str("C-");
+            }
+            if alt {
+                ret.push_str("A-");
+            }
+            ret.push_str(&name);
+            ret.push('>');
+        } else {
+            ret.push_str(&name);
+        }
+    }
+    // Trailing whitespace doesn't survive saving.
+    if ret.ends_with(' ') {
+        ret.pop();
+        ret.push_str("<Space>");
+    }
+    ret
+}
+
+/// Reads keys written by `to_text`.
+pub fn parse(text: &str) -> Result<Vec<KeyEvent>, String> {
+    let mut ret = vec![];
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if c != '<' {
+            ret.push(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+        let Some(end) = rest.find('>') else {
+            return Err(format!("Unclosed < in {text}"));
+        };
+        let mut name = &rest[1..end];
+        let mut modifiers = KeyModifiers::NONE;
+        loop {
+            if let Some(n) = name.strip_prefix("C-").filter(|n| !n.is_empty()) {
+                modifiers |= KeyModifiers::CONTROL;
+                name = n;
+            } else if let Some(n) = name.strip_prefix("A-").filter(|n| !n.is_empty()) {
+                modifiers |= KeyModifiers::ALT;
+                name = n;
+            } else {
+                break;
+            }
+        }
+        let Some(code) = parse_name(name) else {
+            return Err(format!("Unknown key {}", &rest[..=end]));
+        };
+        ret.push(KeyEvent::new(code, modifiers));
+        rest = &rest[end + 1..];
+    }
+    Ok(ret)
+}
+
+#[derive(Default)]
+pub struct Macros {
+    /// Recorded macros by register.
+    pub named: BTreeMap<char, Vec<KeyEvent>>,
+    /// Macros of the project by register.
+    pub project: BTreeMap<char, Vec<KeyEvent>>,
+    /// Register being recorded into and the keys so far.
+    pub recording: Option<(char, Vec<KeyEvent>)>,
+    /// Keys to play after the current key.
+    pub playback: Vec<KeyEvent>,
+    /// Keys played since the last typed key.
+    pub played: usize,
+}
+
+impl Macros {
+    /// Parses `*macros` lines, or the `macros` file.
+    fn parse_lines(lines: &[String]) -> Result<BTreeMap<char, Vec<KeyEvent>>, String> {
+        let mut ret = BTreeMap::new();
+        for (i, line) in lines.iter().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut chars = line.chars();
+            let (Some(reg), Some(' ')) = (chars.next(), chars.next()) else {
+                return Err(format!("Line {}: expected <register> <keys>", i + 1));
+            };
+            if !reg.is_ascii_lowercase() {
+                return Err(format!("Line {}: register should be a to z", i + 1));
+            }
+            let keys = parse(chars.as_str()).map_err(|e| format!("Line {}: {e}", i + 1))?;
+            ret.insert(reg, keys);
+        }
+        Ok(ret)
+    }
+
+    /// Loads the saved macros of the user and of the project.
+    pub fn load() -> Macros {
+        let mut ret = Macros::default();
+        for dir in config::dirs() {
+            let Ok(contents) = fs::read_to_string(dir.join("macros")) else {
+                continue;
+            };
+            let lines: Vec<String> = contents.lines().map(|l| l.to_string()).collect();
+            if let Ok(named) = Macros::parse_lines(&lines) {
+                if config::in_project(&dir) {
+                    ret.project = named;
+                } else {
+                    ret.named = named;
+                }
+            }
+        }
+        ret
+    }
+
+    /// The macro in `reg`, the user's own before the project's.
+    pub fn get(&self, reg: char) -> Option<&Vec<KeyEvent>> {
+        self.named.get(&reg).or_else(|| self.project.get(&reg))
+    }
+
+    /// Lines of `*macros`. Project macros come last as comments.
+    pub fn lines(&self) -> Vec<String> {
+        let mut ret: Vec<String> = self
+            .named
+            .iter()
+            .map(|(reg, keys)| format!("{reg} {}", to_text(keys)))
+            .collect();
+        if !self.project.is_empty() {
+            ret.push(format!(
+                "# Project macros, from ./{}/macros:",
+                config::PROJECT_DIR
+            ));
+            ret.extend(
+                self.project
+                    .iter()
+                    .map(|(reg, keys)| format!("# {reg} {}", to_text(keys))),
+            );
+        }
+        if ret.is_empty() {
+            ret.push(String::new());
+        }
+        ret
+    }
+}
+
+impl Buffer {
+    /// Starts recording into `reg`.
+    pub fn start_recording(&mut self, reg: char) {
+        if !reg.is_ascii_lowercase() {
+            return;
+        }
+        self.macros.recording = Some((reg, vec![]));
+        self.alert = Alert::new(&[format!("Recording @{reg}")], 1_000_000);
+    }
+
+    pub fn stop_recording(&mut self) {
+        let Some((reg, keys)) = self.macros.recording.take() else {
+            return;
+        };
+        self.alert = Alert::new(&[format!("Recorded @{reg}: {}", to_text(&keys))], 2_000_000);
+        self.macros.named.insert(reg, keys);
+    }
+
+    /// Adds a typed key to the macro being recorded. Keys that start or stop
+    /// the recording are left out.
+    pub fn record_key(&mut self, key: KeyEvent, was_recording: bool) {
+        self.macros.played = 0;
+        if let Some((_, keys)) = &mut self.macros.recording
+            && was_recording
+        {
+            keys.push(key);
+        }
+    }
+
+    /// Adds pasted text to the macro being recorded, as the keys typing it.
+    /// Prompts only take the first line. Pastes in nav and visual mode, where
+    /// typed keys are commands, can't be typed and aren't recorded.
+    pub fn record_paste(&mut self, text: &str) {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        let text = match self.mode {
+            Mode::Nav | Mode::Visual(_) | Mode::Confirm => return,
+            Mode::Find | Mode::ReplaceStr => text.lines().next().unwrap_or(""),
+            m if m.show_temp() => text.lines().next().unwrap_or(""),
+            _ => &text,
+        };
+        let Some((_, keys)) = &mut self.macros.recording else {
+            return;
+        };
+        keys.extend(text.chars().map(|c| match c {
+            '\n' => KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE),
+            c => KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE),
+        }));
+    }
+
+    /// Queues the macro in `reg` to be played after the current key.
+    pub fn play_macro(&mut self, reg: char) {
+        match self.macros.get(reg).cloned() {
+            Some(keys) => self.macros.playback.extend(keys),
+            None => self.alert = Alert::new(&[format!("No macro @{reg}")], 1_000_000),
+        }
+    }
+
+    /// Takes the queued keys to play.
+    pub fn take_playback(&mut self) -> Option<Vec<KeyEvent>> {
+        if self.macros.playback.is_empty() {
+            return None;
+        }
+        let keys = std::mem::take(&mut self.macros.playback);
+        self.macros.played += keys.len();
+        if self.macros.played > PLAYBACK_LIMIT {
+            self.alert = Alert::new(&["Macro stopped, too many keys".to_string()], 2_000_000);
+            return None;
+        }
+        Some(keys)
+    }
+
+    /// Applies the edited `*macros` and saves them.
+    pub fn macros_write(&mut self) {
+        let named = match Macros::parse_lines(&self.contents) {
+            Ok(n) => n,
+            Err(e) => {
+                self.alert = Alert::new(&[e], 3_000_000);
+                return;
+            }
+        };
+        self.macros.named = named;
+        self.contents = self.macros.lines();
+        self.update_highlighting();
+        let Some(dir) = config::user_dir() else {
+            return;
+        };
+        let own: Vec<String> = self
+            .contents
+            .iter()
+            .filter(|l| !l.starts_with('#'))
+            .cloned()
+            .collect();
+        let res = fs::create_dir_all(&dir)
+            .and_then(|_| fs::write(dir.join("macros"), own.join("\n") + "\n"));
+        let msg = match res {
+            Ok(()) => format!("Saved {} macros", self.macros.named.len()),
+            Err(e) => format!("Couldn't save macros: {e}"),
+        };
+        self.alert = Alert::new(&[msg], 1_000_000);
+    }
+}
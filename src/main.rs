@@ -14,15 +14,33 @@
 pub mod buffer;
 use buffer::*;
 pub mod nav;
+pub mod operator;
 use nav::*;
 pub mod autocomplete;
+pub mod brackets;
+pub mod clipboard;
+pub mod config;
 pub mod direx;
+pub mod folds;
+pub mod fuzzy;
+pub mod ignore;
 pub mod languages;
+pub mod macros;
+pub mod marks;
+pub mod paths;
+pub mod prompt;
+pub mod regex;
+pub mod registers;
+pub mod repeat;
+pub mod replace;
+pub mod search;
 pub mod snippets;
+pub mod visual;
+use visual::handle_visual;
 
 use crossterm::{
-    event::{self, Event, KeyCode, KeyModifiers},
-    terminal,
+    event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
+    execute, terminal,
 };
 
 use std::{
@@ -46,6 +64,28 @@ pub fn is_close_bracket(ch: char) -> bool {
     matches!(ch, ')' | ']' | '}')
 }
 
+/// Builds a command that runs `cmd` in the system shell.
+pub fn shell_command(cmd: &str) -> Command {
+    let mut command = Command::new(if cfg!(target_os = "windows") {
+        if cfg!(feature = "powershell") {
+            "pwsh"
+        } else {
+            "cmd"
+        }
+    } else {
+        "sh"
+    });
+    command.arg("-c").arg(cmd);
+    command
+}
+
+/// Resets the terminal and hides the cursor. The reset turns bracketed paste
+/// off, so it is turned back on.
+fn reset_terminal() {
+    print!("\x1bc\x1b[?25l");
+    _ = execute!(io::stdout(), event::EnableBracketedPaste);
+}
+
 /** Generates match statement to support autopairs.
 The macro takes the form of:
 ```
@@ -140,1126 +180,1212 @@ impl Mods {
     }
 }
 
-pub fn main() {
-    let mut stdout = io::stdout();
-    let mut args = args().peekable();
-    let mut processing_time: u128 = 0;
-    let mut printing_time: u128 = 0;
-    _ = args.next();
-    let path = if args.peek().is_some() {
-        args.collect::<Vec<String>>().join(" ")
-    } else {
-        String::from("*scratch")
-    };
-    if path.ends_with(".exe") {
-        println!("You shouldn't do this");
-        return;
+/// What the main loop does after a key is handled.
+pub enum Flow {
+    /// Print the buffer.
+    Print,
+    /// Skip printing, as nothing else on screen changed.
+    Skip,
+    /// Quit the editor.
+    Quit,
+}
+
+/// Keeps the buffer in step after a key, typed or played back.
+fn after_key(buf: &mut Buffer, prev_mode: Mode) {
+    buf.track_lines();
+    buf.reveal_cursor();
+    if buf.mode != prev_mode {
+        buf.reset_prompt();
     }
-    let mut buf = Buffer::new(&path);
-    print!("\x1bc\x1b[?25l");
-    _ = terminal::enable_raw_mode();
-    buf.save();
-    buf.add_tokens();
-    print!("Press any key (ideally esc)...");
-    'ed: loop {
-        let (widthu, heightu) = terminal::size().expect("terminal should have size");
-        let width = widthu as usize;
-        let height = heightu as usize;
-        let event = event::read().expect("there should be an event upon reading");
-        let start = Instant::now();
-        if let Event::Key(key) = event {
-            if key.kind != event::KeyEventKind::Release {
-                let mods = key.modifiers.iter();
-                let mut has_alt = false;
-                let mut has_ctrl = false;
-                mods.for_each(|m| {
-                    if m == KeyModifiers::ALT {
-                        has_alt = true;
-                    }
-                    if m == KeyModifiers::CONTROL {
-                        has_ctrl = true;
-                    }
-                });
-                let modifiers = Mods::to_mods(has_alt, has_ctrl);
-                let return_mode = if let Some(BimVar::Bool(true)) = buf.vars.get("ret-to-nav") {
-                    Mode::Nav
-                } else {
-                    Mode::Default
-                };
-                if buf.mode == Mode::Nav {
-                    if handle_nav(&mut buf, key, &modifiers, height, width) {
-                        break 'ed;
-                    }
-                } else {
-                    match modifiers {
-                        Mods::None => match key.code {
-                            KeyCode::Esc => {
-                                if cfg!(feature = "nav-pro") {
-                                    buf.mode = Mode::Nav;
-                                } else {
-                                    buf.mode = Mode::Default;
-                                }
-                            }
-                            KeyCode::Backspace => match buf.mode {
-                                Mode::Find => {
-                                    buf.find_str.pop();
-                                }
-                                Mode::ReplaceStr => {
-                                    buf.replace_str.pop();
-                                }
-                                m if m.show_temp() => {
-                                    buf.temp_str.pop();
-                                }
-                                _ => {
-                                    buf.backspace();
-                                }
-                            },
-                            KeyCode::Delete => {
-                                buf.move_right();
-                                buf.backspace();
-                            }
-                            KeyCode::Enter => {
-                                match buf.mode {
-                                    Mode::Find | Mode::ReplaceStr => {
-                                        buf.mode = return_mode;
-                                    }
-                                    Mode::Indent => {
-                                        buf.mode = return_mode;
-                                        buf.temp_str.clear();
-                                    }
-                                    Mode::Switch => {
-                                        buf.mode = Mode::from_string(&buf.temp_str);
-                                        buf.temp_str.clear();
-                                    }
-                                    Mode::Goto => {
-                                        if let Ok(lineno) = buf.temp_str.parse::<usize>() {
-                                            if lineno < buf.contents.len() {
-                                                if lineno != 0 {
-                                                    buf.cursor_pos.line = lineno - 1;
-                                                } else {
-                                                    buf.cursor_pos.line = 0;
-                                                }
-                                                if buf.contents[buf.cursor_pos.line].chars().count()
-                                                    < buf.cursor_pos.idx
-                                                {
-                                                    buf.cursor_pos.idx = buf.contents
-                                                        [buf.cursor_pos.line]
-                                                        .chars()
-                                                        .count();
-                                                }
-                                            }
-                                        } else {
-                                            buf.alert = Alert::new(
-                                                &[String::from("Inval line num")],
-                                                1_000_000,
-                                            );
-                                        }
-                                        buf.temp_str.clear();
-                                        buf.mode = return_mode;
-                                    }
-                                    Mode::Copy => {
-                                        match buf.decode_from_to() {
-                                            Ok((f, t)) => {
-                                                let paste_contents =
-                                                    &buf.contents.clone()[f - 1..t];
-                                                buf.persistent_buffer.clear();
-                                                for (i, l) in paste_contents.iter().enumerate() {
-                                                    buf.contents.insert(
-                                                        buf.cursor_pos.line + i,
-                                                        l.to_string(),
-                                                    );
-                                                    buf.persistent_buffer.push(l.to_string());
-                                                }
-                                                buf.update_highlighting();
-                                            }
-                                            Err(e) => {
-                                                buf.alert = Alert::new(&[e], 1_000_000);
-                                            }
-                                        }
-                                        buf.temp_str.clear();
-                                        buf.mode = return_mode;
-                                    }
-                                    Mode::KillLines => {
-                                        match buf.decode_from_to() {
-                                            Ok((f, t)) => {
-                                                let mut i = f;
-                                                while i <= t {
-                                                    buf.contents.remove(f - 1);
-                                                    i += 1;
-                                                }
-                                                if buf.contents.is_empty() {
-                                                    buf.contents.push(String::from("\n"));
-                                                }
-                                                if buf.cursor_pos.line >= buf.contents.len()
-                                                    && !buf.contents.is_empty()
-                                                {
-                                                    buf.cursor_pos.line = buf.contents.len() - 1;
-                                                }
-                                                buf.update_highlighting();
-                                            }
-                                            Err(e) => {
-                                                buf.alert = Alert::new(&[e], 1_000_000);
-                                            }
-                                        }
-                                        buf.temp_str.clear();
-                                        buf.mode = return_mode;
-                                    }
-                                    Mode::OpenFile => {
-                                        buf.save();
-                                        if buf.temp_str.ends_with(".exe") {
-                                            buf.alert = Alert::new(
-                                                &[String::from("You shouldn't do that")],
-                                                1_000_000,
-                                            );
-                                            continue;
-                                        }
-                                        buf.buffer_history.hist.push(buf.temp_str.clone());
-                                        buf.buffer_history.head = buf.buffer_history.hist.len() - 1;
-                                        buf.filepath = buf.temp_str.clone();
-                                        buf.reload_file();
-                                        buf.mode = return_mode;
-                                        if buf.cursor_pos.line >= buf.contents.len() {
-                                            buf.cursor_pos.line = buf.contents.len() - 1;
-                                        }
-                                        if buf.cursor_pos.idx
-                                            > buf.contents[buf.cursor_pos.line].chars().count()
-                                        {
-                                            buf.cursor_pos.idx =
-                                                buf.contents[buf.cursor_pos.line].chars().count();
-                                        }
-                                    }
-                                    Mode::Snippet => {
-                                        let sniplines = buf.snippets.query(&buf.temp_str);
-                                        if sniplines.is_empty() {
-                                            buf.alert = Alert::new(
-                                                &["Invalid request".to_string()],
-                                                1_000_000,
-                                            );
-                                        }
-                                        for (i, l) in sniplines.iter().enumerate() {
-                                            let mut ins_line = String::new();
-                                            for _ in 0..buf.lang.indent_size() * buf.indent_lvl {
-                                                ins_line.push(' ');
-                                            }
-                                            ins_line.push_str(l);
-                                            buf.contents
-                                                .insert(buf.cursor_pos.line + i + 1, ins_line);
-                                        }
-                                        buf.update_highlighting();
-                                        buf.mode = return_mode;
-                                    }
-                                    Mode::Tee => {
-                                        buf.mode = return_mode;
-                                    }
-                                    Mode::Command => {
-                                        match Command::new(if cfg!(target_os = "windows") {
-                                            if cfg!(feature = "powershell") {
-                                                "pwsh"
-                                            } else {
-                                                "cmd"
-                                            }
-                                        } else {
-                                            "sh"
-                                        })
-                                        .arg("-c")
-                                        .arg(buf.temp_str.clone())
-                                        .output()
-                                        {
-                                            Ok(o) => {
-                                                let stdout_contents = String::from_utf8(o.stdout)
-                                                    .unwrap_or("".to_string());
-                                                _ = stdout_contents.trim();
-                                                let stderr_contents = String::from_utf8(o.stderr)
-                                                    .unwrap_or("".to_string());
-                                                _ = stderr_contents.trim();
-                                                buf.alert = Alert::new(
-                                                    &[
-                                                        stdout_contents,
-                                                        stderr_contents,
-                                                        format!("{}", o.status),
-                                                    ],
-                                                    5_000_000,
-                                                );
-                                            }
-                                            Err(_) => {
-                                                buf.alert = Alert::new(
-                                                    &["Command failed".to_string()],
-                                                    1_000_000,
-                                                );
-                                            }
-                                        }
+    buf.sync_snippet();
+}
 
-                                        buf.mode = return_mode;
-                                        buf.temp_str.clear();
+/// Handles a key press.
+pub fn handle_key(buf: &mut Buffer, key: KeyEvent, width: usize, height: usize) -> Flow {
+    let mods = key.modifiers.iter();
+    let mut has_alt = false;
+    let mut has_ctrl = false;
+    mods.for_each(|m| {
+        if m == KeyModifiers::ALT {
+            has_alt = true;
+        }
+        if m == KeyModifiers::CONTROL {
+            has_ctrl = true;
+        }
+    });
+    let modifiers = Mods::to_mods(has_alt, has_ctrl);
+    let return_mode = if let Some(BimVar::Bool(true)) = buf.vars.get("ret-to-nav") {
+        Mode::Nav
+    } else {
+        Mode::Default
+    };
+    if buf.mode == Mode::Nav {
+        if handle_nav(buf, key, &modifiers, height, width) {
+            return Flow::Quit;
+        }
+    } else if let Mode::Visual(kind) = buf.mode {
+        handle_visual(buf, kind, key, &modifiers, height, width);
+    } else {
+        match modifiers {
+            Mods::None => match key.code {
+                KeyCode::Esc => {
+                    if cfg!(feature = "nav-pro") {
+                        buf.mode = Mode::Nav;
+                    } else {
+                        buf.mode = Mode::Default;
+                    }
+                }
+                KeyCode::Backspace => match buf.mode {
+                    Mode::Find => {
+                        buf.find_str.pop();
+                    }
+                    Mode::ReplaceStr => {
+                        buf.replace_str.pop();
+                    }
+                    m if m.show_temp() => {
+                        buf.prompt_backspace();
+                        buf.picker_idx = 0;
+                    }
+                    _ => {
+                        if !buf.clear_placeholder() {
+                            buf.backspace();
+                        }
+                    }
+                },
+                KeyCode::Delete if buf.mode.show_temp() => {
+                    buf.prompt_delete();
+                    buf.picker_idx = 0;
+                }
+                KeyCode::Delete => {
+                    buf.move_right();
+                    buf.backspace();
+                }
+                KeyCode::Enter => {
+                    if buf.mode.show_temp() {
+                        buf.record_prompt();
+                    }
+                    match buf.mode {
+                        Mode::Find | Mode::ReplaceStr => {
+                            buf.mode = return_mode;
+                        }
+                        Mode::Indent => {
+                            buf.mode = return_mode;
+                            buf.temp_str.clear();
+                        }
+                        Mode::Switch => {
+                            buf.mode = Mode::from_string(&buf.temp_str);
+                            buf.temp_str.clear();
+                        }
+                        Mode::Goto => {
+                            if let Ok(lineno) = buf.temp_str.parse::<usize>() {
+                                if lineno < buf.contents.len() {
+                                    buf.push_jump();
+                                    if lineno != 0 {
+                                        buf.cursor_pos.line = lineno - 1;
+                                    } else {
+                                        buf.cursor_pos.line = 0;
                                     }
-                                    _ => {
-                                        if let Some('}' | ']' | ')') = buf.contents
-                                            [buf.cursor_pos.line]
-                                            .chars()
-                                            .nth(buf.cursor_pos.idx)
-                                        {
-                                            if buf.indent_lvl != 0 {
-                                                buf.indent_lvl -= 1;
-                                            }
-                                            if buf.cursor_pos.idx != 0 {
-                                                let old = buf.contents[buf.cursor_pos.line].clone();
-                                                buf.contents[buf.cursor_pos.line].clear();
-                                                let mut chrs = old.chars().enumerate().peekable();
-                                                'pushing: loop {
-                                                    match chrs.peek() {
-                                                        Some((i, ch))
-                                                            if *i != buf.cursor_pos.idx =>
-                                                        {
-                                                            buf.contents[buf.cursor_pos.line]
-                                                                .push(*ch);
-                                                        }
-                                                        _ => {
-                                                            break 'pushing;
-                                                        }
-                                                    }
-                                                    _ = chrs.next();
-                                                }
-                                                let linect: String = chrs.map(|(_, c)| c).collect();
-                                                buf.newline_below(&linect);
-                                            }
-                                            buf.move_up();
-                                            buf.indent_lvl += 1;
-                                            buf.newline_below("");
-                                        } else {
-                                            let old = buf.contents[buf.cursor_pos.line].clone();
-                                            buf.contents[buf.cursor_pos.line].clear();
-                                            let mut chrs = old.chars().enumerate().peekable();
-                                            'pushing: loop {
-                                                match chrs.peek() {
-                                                    Some((i, ch)) if *i != buf.cursor_pos.idx => {
-                                                        buf.contents[buf.cursor_pos.line].push(*ch);
-                                                    }
-                                                    _ => {
-                                                        break 'pushing;
-                                                    }
-                                                }
-                                                _ = chrs.next();
-                                            }
-                                            let linect: String = chrs.map(|(_, c)| c).collect();
-                                            buf.newline_below(&linect);
-                                        }
+                                    if buf.contents[buf.cursor_pos.line].chars().count()
+                                        < buf.cursor_pos.idx
+                                    {
+                                        buf.cursor_pos.idx =
+                                            buf.contents[buf.cursor_pos.line].chars().count();
                                     }
-                                };
-                            }
-                            KeyCode::BackTab => {
-                                if buf.indent_lvl > 0 {
-                                    buf.indent_lvl -= 1;
-                                    for _ in 0..buf.lang.indent_size() {
-                                        buf.fast_backspace();
+                                    buf.reveal_cursor();
+                                }
+                            } else {
+                                buf.alert =
+                                    Alert::new(&[String::from("Inval line num")], 1_000_000);
+                            }
+                            buf.temp_str.clear();
+                            buf.mode = return_mode;
+                        }
+                        Mode::Copy => {
+                            match buf.decode_from_to() {
+                                Ok((f, t)) => {
+                                    let paste_contents = buf.contents[f - 1..t].to_vec();
+                                    for (i, l) in paste_contents.iter().enumerate() {
+                                        buf.contents.insert(buf.cursor_pos.line + i, l.to_string());
                                     }
+                                    buf.yank(paste_contents);
                                     buf.update_highlighting();
                                 }
-                            }
-                            KeyCode::Char(c) => {
-                                match buf.mode {
-                                    Mode::Find => {
-                                        buf.find_str.push(c);
-                                        continue;
-                                    }
-                                    Mode::ReplaceStr => {
-                                        buf.replace_str.push(c);
-                                        continue;
-                                    }
-                                    m if m.show_temp() => {
-                                        buf.temp_str.push(c);
-                                        continue;
+                                Err(e) => {
+                                    buf.alert = Alert::new(&[e], 1_000_000);
+                                }
+                            }
+                            buf.temp_str.clear();
+                            buf.mode = return_mode;
+                        }
+                        Mode::KillLines => {
+                            match buf.decode_from_to() {
+                                Ok((f, t)) => {
+                                    let killed: Vec<String> =
+                                        buf.contents.drain(f - 1..t).collect();
+                                    buf.kill(killed);
+                                    if buf.contents.is_empty() {
+                                        buf.contents.push(String::from("\n"));
                                     }
-                                    _ => {
-                                        buf.type_char(c);
-                                    }
-                                }
-                                match c {
-                                    '{' | '}' | '[' | ']' | '(' | ')'
-                                        if buf.mode != Mode::Paste =>
+                                    if buf.cursor_pos.line >= buf.contents.len()
+                                        && !buf.contents.is_empty()
                                     {
-                                        autopair!(
-                                            buf, c,
-                                            '{', '}';
-                                            '[', ']';
-                                            '(', ')'
-                                        );
-                                    }
-                                    _ => {}
-                                }
-                                if buf.mode == Mode::Replace {
-                                    buf.move_right();
-                                    buf.backspace();
-                                }
-                            }
-                            KeyCode::Left => {
-                                buf.move_left();
-                            }
-                            KeyCode::Right => {
-                                buf.move_right();
-                            }
-                            KeyCode::Up => {
-                                buf.move_up();
-                            }
-                            KeyCode::Down => {
-                                buf.move_down();
-                            }
-                            KeyCode::Home => {
-                                buf.cursor_pos.idx = 0;
-                            }
-                            KeyCode::End => {
-                                if !buf.contents[buf.cursor_pos.line].is_empty() {
-                                    buf.cursor_pos.idx =
-                                        buf.contents[buf.cursor_pos.line].chars().count();
-                                }
-                            }
-                            KeyCode::Tab => {
-                                (0..buf.lang.indent_size()).for_each(|_| {
-                                    buf.contents[buf.cursor_pos.line]
-                                        .insert(buf.cursor_pos.idx, ' ');
-                                });
-                                buf.cursor_pos.idx += buf.lang.indent_size();
-                                buf.indent_lvl += 1;
-                                buf.update_highlighting();
-                            }
-                            _ => {}
-                        },
-                        Mods::Alt => match key.code {
-                            KeyCode::Char('q') => {
-                                if buf.buffer_history.hist.len() < 2 {
-                                    break 'ed;
-                                } else {
-                                    buf.buffer_history.hist.remove(buf.buffer_history.head);
-                                    if buf.buffer_history.head > 0 {
-                                        buf.buffer_history.head -= 1;
-                                    }
-                                    buf.filepath =
-                                        buf.buffer_history.hist[buf.buffer_history.head].clone();
-                                    buf.reload_file();
-                                    buf.mode = return_mode;
-                                    if buf.cursor_pos.line >= buf.contents.len() {
                                         buf.cursor_pos.line = buf.contents.len() - 1;
                                     }
-                                    if buf.cursor_pos.idx
-                                        > buf.contents[buf.cursor_pos.line].chars().count()
-                                    {
-                                        buf.cursor_pos.idx =
-                                            buf.contents[buf.cursor_pos.line].chars().count();
-                                    }
-                                    let mut ret: Vec<String> = vec![];
-                                    for (idx, s) in buf.buffer_history.hist.iter().enumerate() {
-                                        if idx == buf.buffer_history.head {
-                                            ret.push(format!("> {s}"));
-                                        } else {
-                                            ret.push(format!("  {s}"));
-                                        }
-                                    }
-                                    buf.alert = Alert::new(&ret, 5_000_000);
-                                }
-                            }
-                            KeyCode::Char('Q') => {
-                                break 'ed;
-                            }
-                            KeyCode::Char('s') => {
-                                buf.save();
-                            }
-                            KeyCode::Char('b') | KeyCode::End => {
-                                let linect = buf.contents.len();
-                                buf.cursor_pos.line = if linect == 0 { 0 } else { linect - 1 };
-                                buf.cursor_pos.idx = 0;
-                            }
-                            KeyCode::Char('t') | KeyCode::Home => {
-                                buf.cursor_pos.line = 0;
-                                buf.cursor_pos.idx = 0;
-                            }
-                            // My custom keybinds
-                            KeyCode::Char('c') => {
-                                buf.move_left();
-                            }
-                            KeyCode::Char('i') => {
-                                buf.move_right();
-                            }
-                            KeyCode::Char('e') => {
-                                buf.move_up();
-                            }
-                            KeyCode::Char('a') => {
-                                buf.move_down();
-                            }
-                            KeyCode::Char('u') => {
-                                if buf.cursor_pos.line >= height {
-                                    buf.cursor_pos.line -= height;
-                                } else {
-                                    buf.cursor_pos.line = 0;
-                                }
-                                let linelen = buf.contents[buf.cursor_pos.line].chars().count();
-                                if buf.cursor_pos.idx > linelen {
-                                    buf.cursor_pos.idx = linelen;
-                                }
-                            }
-                            KeyCode::Char('d') => {
-                                if buf.cursor_pos.line + height >= buf.contents.len() {
-                                    buf.cursor_pos.line = buf.contents.len();
-                                    if buf.cursor_pos.line != 0 {
-                                        buf.cursor_pos.line -= 1;
-                                    }
-                                } else {
-                                    buf.cursor_pos.line += height;
-                                }
-                                let linelen = buf.contents[buf.cursor_pos.line].chars().count();
-                                if buf.cursor_pos.idx > linelen {
-                                    buf.cursor_pos.idx = linelen;
+                                    buf.update_highlighting();
                                 }
+                                Err(e) => {
+                                    buf.alert = Alert::new(&[e], 1_000_000);
+                                }
+                            }
+                            buf.temp_str.clear();
+                            buf.mode = return_mode;
+                        }
+                        Mode::OpenFile => {
+                            buf.open_prompt(return_mode);
+                        }
+                        Mode::Snippet => {
+                            // An empty query only picks a snippet once the selection moved.
+                            let picked = !buf.temp_str.trim().is_empty() || buf.picker_moved;
+                            let request = match buf.snippet_candidates().get(buf.picker_idx) {
+                                Some(c) if picked => c.text.clone(),
+                                _ => buf.temp_str.clone(),
+                            };
+                            let sniplines = buf.snippets.query(&request);
+                            if sniplines.is_empty() {
+                                buf.alert = Alert::new(&["Invalid request".to_string()], 1_000_000);
+                            }
+                            let shell = buf.snippets.shell(&request);
+                            if buf.insert_snippet(&sniplines, shell) {
+                                buf.mode = Mode::Default;
+                            } else {
+                                buf.mode = return_mode;
                             }
-                            KeyCode::Char('l') => {
-                                if buf.contents.len() == 1 {
-                                    buf.contents[0].clear();
-                                    buf.cursor_pos.idx = 0;
-                                } else {
-                                    buf.contents.remove(buf.cursor_pos.line);
-                                    buf.cursor_pos.idx = 0;
+                        }
+                        Mode::Tee => {
+                            buf.mode = return_mode;
+                        }
+                        Mode::FindFile => {
+                            if let Some(c) = buf.file_candidates().get(buf.picker_idx) {
+                                let path = c.text.clone();
+                                buf.open_file(&path);
+                            }
+                            buf.temp_str.clear();
+                            buf.mode = return_mode;
+                        }
+                        Mode::ReplaceAll => {
+                            let replacement = std::mem::take(&mut buf.temp_str);
+                            buf.mode = return_mode;
+                            buf.replace_preview(&replacement);
+                        }
+                        Mode::Filter => {
+                            let cmd = std::mem::take(&mut buf.temp_str);
+                            buf.filter_selection(&cmd);
+                            buf.mode = Mode::Nav;
+                        }
+                        Mode::Grep => {
+                            let query = std::mem::take(&mut buf.temp_str);
+                            buf.mode = return_mode;
+                            buf.grep(&query);
+                        }
+                        Mode::DirexPath => {
+                            buf.direx_finish();
+                            buf.mode = return_mode;
+                        }
+                        Mode::Confirm => {
+                            buf.confirm(false);
+                            buf.mode = return_mode;
+                        }
+                        Mode::Command => {
+                            match shell_command(&buf.temp_str).output() {
+                                Ok(o) => {
+                                    let stdout_contents =
+                                        String::from_utf8(o.stdout).unwrap_or("".to_string());
+                                    _ = stdout_contents.trim();
+                                    let stderr_contents =
+                                        String::from_utf8(o.stderr).unwrap_or("".to_string());
+                                    _ = stderr_contents.trim();
+                                    buf.alert = Alert::new(
+                                        &[
+                                            stdout_contents,
+                                            stderr_contents,
+                                            format!("{}", o.status),
+                                        ],
+                                        5_000_000,
+                                    );
                                 }
-                                let contentlen = buf.contents.len();
-                                if buf.cursor_pos.line >= contentlen && !buf.contents.is_empty() {
-                                    buf.cursor_pos.line = contentlen - 1;
+                                Err(_) => {
+                                    buf.alert =
+                                        Alert::new(&["Command failed".to_string()], 1_000_000);
                                 }
-                                buf.update_highlighting();
                             }
-                            KeyCode::Char(',') => {
+
+                            buf.mode = return_mode;
+                            buf.temp_str.clear();
+                        }
+                        _ => {
+                            if let Some('}' | ']' | ')') = buf.contents[buf.cursor_pos.line]
+                                .chars()
+                                .nth(buf.cursor_pos.idx)
+                            {
                                 if buf.indent_lvl != 0 {
                                     buf.indent_lvl -= 1;
                                 }
-                            }
-                            KeyCode::Char('.') => {
-                                buf.indent_lvl += 1;
-                            }
-                            KeyCode::Char('I') => {
-                                buf.adjust_indent();
-                                if buf.cursor_pos.idx
-                                    > buf.contents[buf.cursor_pos.line].chars().count()
-                                {
-                                    buf.cursor_pos.idx =
-                                        buf.contents[buf.cursor_pos.line].chars().count();
-                                }
-                            }
-                            KeyCode::Char('<') => {
-                                if buf.mode != Mode::Indent {
-                                    if buf.indent_lvl != 0 {
-                                        buf.indent_lvl -= 1;
-                                        let indent_size = buf.lang.indent_size();
-                                        if buf.cursor_pos.idx >= indent_size {
-                                            buf.cursor_pos.idx -= indent_size;
-                                        }
-                                        let linelen =
-                                            buf.contents[buf.cursor_pos.line].chars().count();
-                                        if buf.cursor_pos.idx > linelen {
-                                            buf.cursor_pos.idx = linelen;
-                                        }
-                                    }
-                                    buf.adjust_indent();
-                                } else {
-                                    match buf.decode_from_to() {
-                                        Ok((f, t)) => {
-                                            let mut prefix = String::new();
-                                            for _ in 0..buf.lang.indent_size() {
-                                                prefix.push(' ');
-                                            }
-                                            for i in (f - 1)..t {
-                                                buf.contents[i] = buf.contents[i]
-                                                    .strip_prefix(&prefix)
-                                                    .unwrap_or(&buf.contents[i])
-                                                    .to_string();
-                                            }
-                                            buf.update_highlighting();
-                                        }
-                                        Err(e) => {
-                                            buf.alert = Alert::new(&[e], 1_000_000);
-                                        }
-                                    }
-                                }
-                            }
-                            KeyCode::Char('>') => {
-                                if buf.mode != Mode::Indent {
-                                    buf.indent_lvl += 1;
-                                    buf.adjust_indent();
-                                    buf.cursor_pos.idx += buf.lang.indent_size();
-                                } else {
-                                    match buf.decode_from_to() {
-                                        Ok((f, t)) => {
-                                            let mut prefix = String::new();
-                                            for _ in 0..buf.lang.indent_size() {
-                                                prefix.push(' ');
+                                if buf.cursor_pos.idx != 0 {
+                                    let old = buf.contents[buf.cursor_pos.line].clone();
+                                    buf.contents[buf.cursor_pos.line].clear();
+                                    let mut chrs = old.chars().enumerate().peekable();
+                                    'pushing: loop {
+                                        match chrs.peek() {
+                                            Some((i, ch)) if *i != buf.cursor_pos.idx => {
+                                                buf.contents[buf.cursor_pos.line].push(*ch);
                                             }
-                                            for i in (f - 1)..t {
-                                                buf.contents[i] =
-                                                    format!("{}{}", prefix, buf.contents[i]);
+                                            _ => {
+                                                break 'pushing;
                                             }
-                                            buf.update_highlighting();
-                                        }
-                                        Err(e) => {
-                                            buf.alert = Alert::new(&[e], 1_000_000);
-                                        }
-                                    }
-                                }
-                            }
-                            KeyCode::Char(';') => {
-                                let mut currline = buf.contents[buf.cursor_pos.line].chars();
-                                let mut spaces = 0;
-                                while currline.next() == Some(' ') {
-                                    spaces += 1;
-                                }
-                                buf.indent_lvl = spaces / buf.lang.indent_size();
-                                buf.cursor_pos.idx = spaces;
-                            }
-                            KeyCode::Char(':') => {
-                                let mut currline = buf.contents[buf.cursor_pos.line].chars();
-                                let mut spaces = 0;
-                                while currline.next() == Some(' ') {
-                                    spaces += 1;
-                                }
-                                buf.indent_lvl = spaces / buf.lang.indent_size();
-                            }
-                            KeyCode::Char('/') => {
-                                if buf.mode == Mode::Find {
-                                    buf.mode = return_mode;
-                                } else {
-                                    buf.mode = Mode::Find;
-                                    buf.find_str.clear();
-                                }
-                            }
-                            KeyCode::Char('n') => {
-                                if buf.move_right() {
-                                    'findfwd: loop {
-                                        let prevpos = buf.cursor_pos;
-                                        if let Some(p) = buf.contents[buf.cursor_pos.line]
-                                            [buf.cursor_pos.idx..]
-                                            .find(buf.find_str.as_str())
-                                        {
-                                            buf.cursor_pos.idx += p;
-                                            buf.cursor_pos.idx += buf.find_str.chars().count();
-                                            break 'findfwd;
-                                        }
-                                        buf.cursor_pos.idx = 0;
-                                        if !buf.move_down() {
-                                            buf.cursor_pos = prevpos;
-                                            break 'findfwd;
                                         }
+                                        _ = chrs.next();
                                     }
+                                    let linect: String = chrs.map(|(_, c)| c).collect();
+                                    buf.newline_below(&linect);
                                 }
-                            }
-                            KeyCode::Char('p') => {
-                                if buf.move_left() {
-                                    'findfwd: loop {
-                                        let prevpos = buf.cursor_pos;
-                                        if let Some(p) = buf.contents[buf.cursor_pos.line]
-                                            [0..buf.cursor_pos.idx]
-                                            .rfind(buf.find_str.as_str())
-                                        {
-                                            buf.cursor_pos.idx = p;
-                                            buf.cursor_pos.idx += buf.find_str.chars().count();
-                                            break 'findfwd;
+                                buf.move_up();
+                                buf.indent_lvl += 1;
+                                buf.newline_below("");
+                            } else {
+                                let old = buf.contents[buf.cursor_pos.line].clone();
+                                buf.contents[buf.cursor_pos.line].clear();
+                                let mut chrs = old.chars().enumerate().peekable();
+                                'pushing: loop {
+                                    match chrs.peek() {
+                                        Some((i, ch)) if *i != buf.cursor_pos.idx => {
+                                            buf.contents[buf.cursor_pos.line].push(*ch);
                                         }
-                                        let status = buf.move_up();
-                                        buf.cursor_pos.idx =
-                                            buf.contents[buf.cursor_pos.line].chars().count();
-                                        if !status {
-                                            buf.cursor_pos = prevpos;
-                                            break 'findfwd;
+                                        _ => {
+                                            break 'pushing;
                                         }
                                     }
+                                    _ = chrs.next();
                                 }
+                                let linect: String = chrs.map(|(_, c)| c).collect();
+                                buf.newline_below(&linect);
                             }
-                            KeyCode::Char('o') => {
-                                buf.newline_below("");
-                            }
-                            KeyCode::Char('O') => {
-                                if buf.move_up() {
-                                    buf.newline_below("");
-                                } else {
-                                    buf.contents.insert(0, String::new());
-                                    buf.cursor_pos.idx = 0;
-                                    buf.update_highlighting();
-                                }
-                            }
-                            KeyCode::Char('x' | 'M') => {
-                                buf.mode = Mode::Switch;
-                                buf.temp_str.clear();
-                            }
-                            KeyCode::Char('h') => {
-                                buf.contents[buf.cursor_pos.line].replace_range(
-                                    (if buf.cursor_pos.idx >= buf.find_str.chars().count() {
-                                        buf.cursor_pos.idx - buf.find_str.chars().count()
-                                    } else {
-                                        0
-                                    })..buf.cursor_pos.idx,
-                                    &buf.replace_str,
+                        }
+                    };
+                }
+                KeyCode::Tab if buf.mode == Mode::OpenFile => {
+                    buf.complete_path();
+                }
+                KeyCode::Tab if matches!(buf.mode, Mode::Snippet | Mode::FindFile) => {
+                    buf.picker_idx += 1;
+                    buf.picker_moved = true;
+                }
+                KeyCode::BackTab if matches!(buf.mode, Mode::Snippet | Mode::FindFile) => {
+                    let len = if buf.mode == Mode::Snippet {
+                        buf.snippet_candidates().len()
+                    } else {
+                        buf.file_candidates().len()
+                    };
+                    buf.picker_idx = buf
+                        .picker_idx
+                        .checked_sub(1)
+                        .unwrap_or(len.saturating_sub(1));
+                    buf.picker_moved = true;
+                }
+                KeyCode::BackTab if buf.snippet_session.is_some() => {
+                    buf.prev_tab_stop();
+                }
+                KeyCode::BackTab => {
+                    if buf.indent_lvl > 0 {
+                        buf.indent_lvl -= 1;
+                        for _ in 0..buf.lang.indent_size() {
+                            buf.fast_backspace();
+                        }
+                        buf.update_highlighting();
+                    }
+                }
+                KeyCode::Char(c) => match buf.mode {
+                    Mode::Find => {
+                        buf.find_str.push(c);
+                        return Flow::Skip;
+                    }
+                    Mode::ReplaceStr => {
+                        buf.replace_str.push(c);
+                        return Flow::Skip;
+                    }
+                    Mode::Snippet | Mode::FindFile => {
+                        buf.prompt_insert(c);
+                        buf.picker_idx = 0;
+                    }
+                    Mode::Confirm => {
+                        buf.confirm(c == 'y');
+                        buf.mode = return_mode;
+                    }
+                    m if m.show_temp() => {
+                        buf.prompt_insert(c);
+                        return Flow::Skip;
+                    }
+                    _ => {
+                        buf.clear_placeholder();
+                        buf.type_char(c);
+                        match c {
+                            '{' | '}' | '[' | ']' | '(' | ')' if buf.mode != Mode::Paste => {
+                                autopair!(
+                                    buf, c,
+                                    '{', '}';
+                                    '[', ']';
+                                    '(', ')'
                                 );
-                                if buf.cursor_pos.idx
-                                    > buf.contents[buf.cursor_pos.line].chars().count()
-                                {
-                                    buf.cursor_pos.idx =
-                                        buf.contents[buf.cursor_pos.line].chars().count();
-                                }
-                                buf.update_highlighting();
-                            }
-                            KeyCode::Char('r') => {
-                                if buf.mode == Mode::ReplaceStr {
-                                    buf.mode = return_mode;
-                                } else {
-                                    buf.mode = Mode::ReplaceStr;
-                                    buf.replace_str.clear();
-                                }
                             }
-                            KeyCode::Char('j') => {
-                                if buf.contents.len() > buf.cursor_pos.line + 1 {
-                                    let l = buf.contents.remove(buf.cursor_pos.line + 1);
-                                    let o = buf.contents[buf.cursor_pos.line].clone();
-                                    buf.contents[buf.cursor_pos.line].clear();
-                                    buf.contents[buf.cursor_pos.line].push_str(o.trim_end());
-                                    buf.contents[buf.cursor_pos.line].push(' ');
-                                    buf.contents[buf.cursor_pos.line].push_str(l.trim());
+                            _ => {}
+                        }
+                        if buf.mode == Mode::Replace {
+                            buf.move_right();
+                            buf.backspace();
+                        }
+                    }
+                },
+                KeyCode::Left if buf.mode.show_temp() => {
+                    buf.prompt_left();
+                }
+                KeyCode::Right if buf.mode.show_temp() => {
+                    buf.prompt_right();
+                }
+                KeyCode::Up if buf.mode.show_temp() => {
+                    buf.history_prev();
+                    buf.picker_idx = 0;
+                }
+                KeyCode::Down if buf.mode.show_temp() => {
+                    buf.history_next();
+                    buf.picker_idx = 0;
+                }
+                KeyCode::Home if buf.mode.show_temp() => {
+                    buf.prompt_home();
+                }
+                KeyCode::End if buf.mode.show_temp() => {
+                    buf.prompt_end();
+                }
+                KeyCode::Left => {
+                    buf.move_left();
+                }
+                KeyCode::Right => {
+                    buf.move_right();
+                }
+                KeyCode::Up => {
+                    buf.move_up();
+                }
+                KeyCode::Down => {
+                    buf.move_down();
+                }
+                KeyCode::Home => {
+                    buf.cursor_pos.idx = 0;
+                }
+                KeyCode::End => {
+                    if !buf.contents[buf.cursor_pos.line].is_empty() {
+                        buf.cursor_pos.idx = buf.contents[buf.cursor_pos.line].chars().count();
+                    }
+                }
+                KeyCode::Tab if buf.snippet_session.is_some() => {
+                    buf.next_tab_stop();
+                }
+                KeyCode::Tab => {
+                    (0..buf.lang.indent_size()).for_each(|_| {
+                        buf.contents[buf.cursor_pos.line].insert(buf.cursor_pos.idx, ' ');
+                    });
+                    buf.cursor_pos.idx += buf.lang.indent_size();
+                    buf.indent_lvl += 1;
+                    buf.update_highlighting();
+                }
+                _ => {}
+            },
+            Mods::Alt => match key.code {
+                KeyCode::Left => {
+                    buf.jump_back();
+                }
+                KeyCode::Right => {
+                    buf.jump_forward();
+                }
+                KeyCode::Char('f') => {
+                    buf.find_file();
+                }
+                KeyCode::Char('m') => {
+                    buf.open_special("*macros");
+                    buf.cursor_pos.line = 0;
+                    buf.cursor_pos.idx = 0;
+                    buf.update_highlighting();
+                }
+                KeyCode::Char('g') => {
+                    buf.mode = Mode::Grep;
+                    buf.temp_str.clear();
+                }
+                KeyCode::Char('q') => {
+                    if buf.buffer_history.hist.len() < 2 {
+                        return Flow::Quit;
+                    } else {
+                        buf.buffer_history.hist.remove(buf.buffer_history.head);
+                        if buf.buffer_history.head > 0 {
+                            buf.buffer_history.head -= 1;
+                        }
+                        buf.filepath = buf.buffer_history.hist[buf.buffer_history.head].clone();
+                        buf.reload_file();
+                        buf.mode = return_mode;
+                        if buf.cursor_pos.line >= buf.contents.len() {
+                            buf.cursor_pos.line = buf.contents.len() - 1;
+                        }
+                        if buf.cursor_pos.idx > buf.contents[buf.cursor_pos.line].chars().count() {
+                            buf.cursor_pos.idx = buf.contents[buf.cursor_pos.line].chars().count();
+                        }
+                        let mut ret: Vec<String> = vec![];
+                        for (idx, s) in buf.buffer_history.hist.iter().enumerate() {
+                            if idx == buf.buffer_history.head {
+                                ret.push(format!("> {s}"));
+                            } else {
+                                ret.push(format!("  {s}"));
+                            }
+                        }
+                        buf.alert = Alert::new(&ret, 5_000_000);
+                    }
+                }
+                KeyCode::Char('Q') => {
+                    return Flow::Quit;
+                }
+                KeyCode::Char('s') => {
+                    buf.save();
+                }
+                KeyCode::Char('b') | KeyCode::End => {
+                    let linect = buf.contents.len();
+                    buf.cursor_pos.line = if linect == 0 { 0 } else { linect - 1 };
+                    buf.cursor_pos.idx = 0;
+                }
+                KeyCode::Char('t') | KeyCode::Home => {
+                    buf.cursor_pos.line = 0;
+                    buf.cursor_pos.idx = 0;
+                }
+                // My custom keybinds
+                KeyCode::Char('c') => {
+                    buf.move_left();
+                }
+                KeyCode::Char('i') => {
+                    buf.move_right();
+                }
+                KeyCode::Char('e') => {
+                    buf.move_up();
+                }
+                KeyCode::Char('a') => {
+                    buf.move_down();
+                }
+                KeyCode::Char('u') => {
+                    if buf.cursor_pos.line >= height {
+                        buf.cursor_pos.line -= height;
+                    } else {
+                        buf.cursor_pos.line = 0;
+                    }
+                    let linelen = buf.contents[buf.cursor_pos.line].chars().count();
+                    if buf.cursor_pos.idx > linelen {
+                        buf.cursor_pos.idx = linelen;
+                    }
+                }
+                KeyCode::Char('d') => {
+                    if buf.cursor_pos.line + height >= buf.contents.len() {
+                        buf.cursor_pos.line = buf.contents.len();
+                        if buf.cursor_pos.line != 0 {
+                            buf.cursor_pos.line -= 1;
+                        }
+                    } else {
+                        buf.cursor_pos.line += height;
+                    }
+                    let linelen = buf.contents[buf.cursor_pos.line].chars().count();
+                    if buf.cursor_pos.idx > linelen {
+                        buf.cursor_pos.idx = linelen;
+                    }
+                }
+                KeyCode::Char('l') => {
+                    let killed = if buf.contents.len() == 1 {
+                        std::mem::take(&mut buf.contents[0])
+                    } else {
+                        buf.contents.remove(buf.cursor_pos.line)
+                    };
+                    buf.cursor_pos.idx = 0;
+                    let contentlen = buf.contents.len();
+                    if buf.cursor_pos.line >= contentlen && !buf.contents.is_empty() {
+                        buf.cursor_pos.line = contentlen - 1;
+                    }
+                    buf.kill(vec![killed]);
+                    buf.update_highlighting();
+                }
+                KeyCode::Char(',') => {
+                    if buf.indent_lvl != 0 {
+                        buf.indent_lvl -= 1;
+                    }
+                }
+                KeyCode::Char('.') => {
+                    buf.indent_lvl += 1;
+                }
+                KeyCode::Char('I') => {
+                    buf.adjust_indent();
+                    if buf.cursor_pos.idx > buf.contents[buf.cursor_pos.line].chars().count() {
+                        buf.cursor_pos.idx = buf.contents[buf.cursor_pos.line].chars().count();
+                    }
+                }
+                KeyCode::Char('<') => {
+                    if buf.mode != Mode::Indent {
+                        if buf.indent_lvl != 0 {
+                            buf.indent_lvl -= 1;
+                            let indent_size = buf.lang.indent_size();
+                            if buf.cursor_pos.idx >= indent_size {
+                                buf.cursor_pos.idx -= indent_size;
+                            }
+                            let linelen = buf.contents[buf.cursor_pos.line].chars().count();
+                            if buf.cursor_pos.idx > linelen {
+                                buf.cursor_pos.idx = linelen;
+                            }
+                        }
+                        buf.adjust_indent();
+                    } else {
+                        match buf.decode_from_to() {
+                            Ok((f, t)) => {
+                                let mut prefix = String::new();
+                                for _ in 0..buf.lang.indent_size() {
+                                    prefix.push(' ');
+                                }
+                                for i in (f - 1)..t {
+                                    buf.contents[i] = buf.contents[i]
+                                        .strip_prefix(&prefix)
+                                        .unwrap_or(&buf.contents[i])
+                                        .to_string();
                                 }
                                 buf.update_highlighting();
                             }
-                            KeyCode::Char('0') => {
-                                buf.indent_lvl = 0;
+                            Err(e) => {
+                                buf.alert = Alert::new(&[e], 1_000_000);
                             }
-                            KeyCode::Char('k') => {
-                                let indent_size = buf.lang.indent_size();
-                                if buf.indent_lvl * indent_size != buf.cursor_pos.idx {
-                                    buf.contents[buf.cursor_pos.line].replace_range(
-                                        (buf.indent_lvl * indent_size)..buf.cursor_pos.idx,
-                                        "",
-                                    );
-                                    buf.cursor_pos.idx = buf.indent_lvl * indent_size;
-                                    buf.update_highlighting();
+                        }
+                    }
+                }
+                KeyCode::Char('>') => {
+                    if buf.mode != Mode::Indent {
+                        buf.indent_lvl += 1;
+                        buf.adjust_indent();
+                        buf.cursor_pos.idx += buf.lang.indent_size();
+                    } else {
+                        match buf.decode_from_to() {
+                            Ok((f, t)) => {
+                                let mut prefix = String::new();
+                                for _ in 0..buf.lang.indent_size() {
+                                    prefix.push(' ');
+                                }
+                                for i in (f - 1)..t {
+                                    buf.contents[i] = format!("{}{}", prefix, buf.contents[i]);
                                 }
-                            }
-                            KeyCode::Char('K') => {
-                                buf.contents[buf.cursor_pos.line].truncate(buf.cursor_pos.idx);
                                 buf.update_highlighting();
                             }
-                            KeyCode::Char('w') => {
-                                while buf.contents[buf.cursor_pos.line]
-                                    .chars()
-                                    .nth(buf.cursor_pos.idx)
-                                    .unwrap_or(' ')
-                                    .is_whitespace()
-                                {
-                                    if !buf.move_right() {
-                                        break;
-                                    }
-                                }
-                                while !buf.contents[buf.cursor_pos.line]
-                                    .chars()
-                                    .nth(buf.cursor_pos.idx)
-                                    .unwrap_or(' ')
-                                    .is_whitespace()
-                                {
-                                    if !buf.move_right() {
-                                        break;
-                                    }
-                                }
-                                buf.move_right();
-                                if buf.cursor_pos.idx == 0 {
-                                    buf.move_left();
-                                }
-                            }
-                            KeyCode::Char('W') => {
-                                while buf.contents[buf.cursor_pos.line]
-                                    .chars()
-                                    .nth(buf.cursor_pos.idx)
-                                    .unwrap_or(' ')
-                                    .is_whitespace()
-                                {
-                                    if !buf.move_left() {
-                                        break;
-                                    }
-                                }
-                                while !buf.contents[buf.cursor_pos.line]
-                                    .chars()
-                                    .nth(buf.cursor_pos.idx)
-                                    .unwrap_or(' ')
-                                    .is_whitespace()
-                                {
-                                    if !buf.move_left() {
-                                        break;
-                                    }
-                                }
-                            }
-                            KeyCode::Char('y') => {
-                                buf.contents.insert(
-                                    buf.cursor_pos.line,
-                                    buf.contents[buf.cursor_pos.line].clone(),
-                                );
-                                buf.persistent_buffer.clear();
-                                buf.persistent_buffer
-                                    .push(buf.contents[buf.cursor_pos.line].clone());
-                                buf.update_highlighting();
-                                buf.move_down();
-                            }
-                            KeyCode::Char('Y') => {
-                                if buf.mode != Mode::Copy {
-                                    buf.mode = Mode::Copy;
-                                    buf.temp_str.clear();
-                                }
-                                let numbuf = format!("{} ", buf.cursor_pos.line + 1);
-                                for c in numbuf.chars() {
-                                    buf.temp_str.push(c);
-                                }
-                            }
-                            KeyCode::Char('C') => {
-                                if buf.mode != Mode::KillLines {
-                                    buf.mode = Mode::KillLines;
-                                    buf.temp_str.clear();
-                                }
-                                let numbuf = format!("{} ", buf.cursor_pos.line + 1);
-                                for c in numbuf.chars() {
-                                    buf.temp_str.push(c);
-                                }
-                            }
-                            KeyCode::Char('A') => {
-                                if buf.cursor_pos.line + 1 < buf.contents.len() {
-                                    buf.contents
-                                        .swap(buf.cursor_pos.line, buf.cursor_pos.line + 1);
-                                    buf.update_highlighting();
-                                    buf.move_down();
-                                }
-                            }
-                            KeyCode::Char('E') => {
-                                if buf.cursor_pos.line != 0 {
-                                    buf.contents
-                                        .swap(buf.cursor_pos.line, buf.cursor_pos.line - 1);
-                                    buf.move_up();
-                                    buf.update_highlighting();
-                                }
-                            }
-                            KeyCode::Char('G') => {
-                                buf.mode = Mode::Goto;
-                                buf.temp_str.clear();
-                            }
-                            KeyCode::Char('S') => {
-                                buf.mode = Mode::Snippet;
-                                buf.temp_str.clear();
-                            }
-                            KeyCode::Char('[') => {
-                                while buf.cursor_pos.line != 0
-                                    && buf.contents[buf.cursor_pos.line].is_empty()
-                                {
-                                    buf.cursor_pos.line -= 1;
-                                }
-                                while buf.cursor_pos.line != 0
-                                    && !buf.contents[buf.cursor_pos.line].is_empty()
-                                {
-                                    buf.cursor_pos.line -= 1;
-                                }
-                                if buf.cursor_pos.idx
-                                    > buf.contents[buf.cursor_pos.line].chars().count()
-                                {
-                                    buf.cursor_pos.idx =
-                                        buf.contents[buf.cursor_pos.line].chars().count();
-                                }
-                            }
-                            KeyCode::Char(']') => {
-                                let content_len = buf.contents.len();
-                                while buf.cursor_pos.line + 1 < content_len
-                                    && !buf.contents[buf.cursor_pos.line].is_empty()
-                                {
-                                    buf.cursor_pos.line += 1;
-                                }
-                                while buf.cursor_pos.line + 1 < content_len
-                                    && buf.contents[buf.cursor_pos.line].is_empty()
-                                {
-                                    buf.cursor_pos.line += 1;
-                                }
-                                if buf.cursor_pos.idx
-                                    > buf.contents[buf.cursor_pos.line].chars().count()
-                                {
-                                    buf.cursor_pos.idx =
-                                        buf.contents[buf.cursor_pos.line].chars().count();
-                                }
-                            }
-                            KeyCode::Char('N') | KeyCode::Char('v') => {
-                                buf.mode = Mode::Nav;
-                                buf.vars
-                                    .insert(String::from("ret-to-nav"), BimVar::Bool(true));
-                                buf.temp_str.clear();
-                            }
-                            KeyCode::Char('-') => {
-                                buf.alert = Alert::new(&[], 1_000_000);
-                            }
-                            KeyCode::Char('T') => {
-                                buf.mode = Mode::Tee;
-                                buf.replace_str.clear();
-                            }
-                            KeyCode::Char(x) if x.is_numeric() => {
-                                let (all_matches, mut querylen) =
-                                    buf.autocomplete.get_candidates(&buf);
-                                let matches: Vec<String> =
-                                    all_matches.iter().take(5).map(|s| s.to_string()).collect();
-                                match x {
-                                    '1' | '2' | '3' | '4' | '5' => {
-                                        let replace_str = match x {
-                                            '1' => matches.get(0).map_or("", |c| c),
-                                            '2' => matches.get(1).map_or("", |c| c),
-                                            '3' => matches.get(2).map_or("", |c| c),
-                                            '4' => matches.get(3).map_or("", |c| c),
-                                            '5' => matches.get(4).map_or("", |c| c),
-                                            _ => {
-                                                querylen = 0;
-                                                ""
-                                            }
-                                        };
-                                        if replace_str.is_empty() {
-                                            continue;
-                                        }
-                                        buf.contents[buf.cursor_pos.line].replace_range(
-                                            buf.cursor_pos.idx.saturating_sub(querylen)
-                                                ..buf.cursor_pos.idx,
-                                            replace_str,
-                                        );
-                                        buf.cursor_pos.idx += replace_str.chars().count();
-                                        buf.cursor_pos.idx -= querylen;
-                                        if buf.cursor_pos.idx
-                                            > buf.contents[buf.cursor_pos.line].chars().count()
-                                        {
-                                            buf.cursor_pos.idx =
-                                                buf.contents[buf.cursor_pos.line].chars().count();
-                                        }
-                                        buf.update_highlighting();
-                                    }
-                                    '6' => {
-                                        let display_matches: Vec<String> = matches
-                                            .iter()
-                                            .enumerate()
-                                            .map(|(i, v)| format!("{}: `{v}`", i + 1))
-                                            .collect();
-                                        buf.alert = Alert::new(&display_matches, 500_000);
-                                    }
-                                    _ => {}
-                                }
-                            }
-                            KeyCode::Char('U') => {
-                                if buf.mode != Mode::Indent {
-                                    buf.mode = Mode::Indent;
-                                    buf.temp_str.clear();
-                                }
-                                let numbuf = format!("{} ", buf.cursor_pos.line + 1);
-                                for c in numbuf.chars() {
-                                    buf.temp_str.push(c);
-                                }
-                            }
-                            KeyCode::Char('!') => {
-                                if buf.mode == Mode::Command {
-                                    buf.mode = return_mode;
-                                    buf.temp_str.clear();
-                                } else {
-                                    buf.mode = Mode::Command;
-                                }
-                            }
-                            KeyCode::Char('P') => {
-                                for (i, l) in buf.persistent_buffer.iter().enumerate() {
-                                    buf.contents.insert(buf.cursor_pos.line + i, l.to_string());
-                                }
-                                buf.update_highlighting();
-                            }
-                            _ => {}
-                        },
-                        Mods::Ctrl => match key.code {
-                            KeyCode::Char('N') => {
-                                buf.mode = Mode::Nav;
-                                buf.vars
-                                    .insert(String::from("ret-to-nav"), BimVar::Bool(true));
-                                buf.temp_str.clear();
-                            }
-                            KeyCode::Char('r') => {
-                                buf.reload_file();
-                                buf.cursor_pos.line = 0;
-                                buf.cursor_pos.idx = 0;
-                                print!("\x1bc\x1b[?25l");
-                            }
-                            KeyCode::Char('z') => {
-                                buf.reload_file();
-                                if buf.cursor_pos.line >= buf.contents.len() {
-                                    buf.cursor_pos.line = buf.contents.len() - 1;
-                                }
-                                if buf.cursor_pos.idx
-                                    > buf.contents[buf.cursor_pos.line].chars().count()
-                                {
-                                    buf.cursor_pos.idx =
-                                        buf.contents[buf.cursor_pos.line].chars().count();
-                                }
-                                print!("\x1bc\x1b[?25l");
-                            }
-                            KeyCode::Backspace => {
-                                while buf.fast_backspace().unwrap_or('a').is_whitespace() {}
-                                let mut last;
-                                'killword: loop {
-                                    last = buf.fast_backspace().unwrap_or(' ');
-                                    if !last.is_alphanumeric() {
-                                        break 'killword;
-                                    }
-                                }
-                                if last == '\n' {
-                                    buf.update_highlighting();
-                                } else {
-                                    buf.type_char(last);
-                                }
+                            Err(e) => {
+                                buf.alert = Alert::new(&[e], 1_000_000);
                             }
-                            KeyCode::Char('o') => {
-                                buf.mode = Mode::OpenFile;
-                                buf.temp_str.clear();
-                            }
-                            KeyCode::Char('y') => {
-                                if buf.top < buf.contents.len() {
-                                    buf.top += 1;
-                                }
-                                buf.move_down();
-                            }
-                            KeyCode::Char('e') => {
-                                if buf.top > 0 {
-                                    buf.top -= 1;
-                                }
-                                buf.move_up();
-                            }
-                            KeyCode::Char('b') => {
-                                let mut ret: Vec<String> = vec![];
-                                for (idx, s) in buf.buffer_history.hist.iter().enumerate() {
-                                    if idx == buf.buffer_history.head {
-                                        ret.push(format!("> {s}"));
-                                    } else {
-                                        ret.push(format!("  {s}"));
-                                    }
-                                }
-                                buf.alert = Alert::new(&ret, 5_000_000);
-                            }
-                            KeyCode::Char('p') => {
-                                if buf.buffer_history.head == 0 {
-                                    buf.buffer_history.head = buf.buffer_history.hist.len();
-                                }
-                                buf.buffer_history.head -= 1;
-                                buf.save();
-                                buf.filepath =
-                                    buf.buffer_history.hist[buf.buffer_history.head].clone();
-                                buf.reload_file();
-                                if buf.cursor_pos.line >= buf.contents.len() {
-                                    buf.cursor_pos.line = buf.contents.len() - 1;
-                                }
-                                if buf.cursor_pos.idx
-                                    > buf.contents[buf.cursor_pos.line].chars().count()
-                                {
-                                    buf.cursor_pos.idx =
-                                        buf.contents[buf.cursor_pos.line].chars().count();
-                                }
-                                buf.alert = Alert::new(&buf.buffer_history.display(), 500_000);
-                            }
-                            KeyCode::Char('n') => {
-                                buf.buffer_history.head += 1;
-                                if buf.buffer_history.head >= buf.buffer_history.hist.len() {
-                                    buf.buffer_history.head = 0;
-                                }
-                                buf.save();
-                                buf.filepath =
-                                    buf.buffer_history.hist[buf.buffer_history.head].clone();
-                                buf.reload_file();
-                                if buf.cursor_pos.line >= buf.contents.len() {
-                                    buf.cursor_pos.line = buf.contents.len() - 1;
-                                }
-                                if buf.cursor_pos.idx
-                                    > buf.contents[buf.cursor_pos.line].chars().count()
-                                {
-                                    buf.cursor_pos.idx =
-                                        buf.contents[buf.cursor_pos.line].chars().count();
-                                }
-                                let mut ret = vec![];
-                                for (idx, s) in buf.buffer_history.hist.iter().enumerate() {
-                                    if idx == buf.buffer_history.head {
-                                        ret.push(format!("> {s}"));
-                                    } else {
-                                        ret.push(format!("  {s}"));
-                                    }
-                                }
-                                buf.alert = Alert::new(&ret, 500_000);
-                            }
-                            KeyCode::Char('d') => {
-                                match buf.buffer_history.hist.iter().position(|x| x == "*direx") {
-                                    Some(i) => {
-                                        buf.buffer_history.head = i;
-                                        buf.save();
-                                    }
-                                    None => {
-                                        buf.save();
-                                        buf.buffer_history.hist.push("*direx".to_string());
-                                        buf.buffer_history.head = buf.buffer_history.hist.len() - 1;
-                                    }
-                                }
-                                buf.filepath = "*direx".to_string();
-                                buf.reload_file();
-                                buf.mode = return_mode;
-                                if buf.cursor_pos.line >= buf.contents.len() {
-                                    buf.cursor_pos.line = buf.contents.len() - 1;
-                                }
-                                if buf.cursor_pos.idx
-                                    > buf.contents[buf.cursor_pos.line].chars().count()
-                                {
-                                    buf.cursor_pos.idx =
-                                        buf.contents[buf.cursor_pos.line].chars().count();
-                                }
-                                buf.update_highlighting();
-                            }
-                            KeyCode::Char('f') => {
-                                if buf.filepath == "*direx" {
-                                    buf.save();
-                                    let newpath = &buf.contents[buf.cursor_pos.line];
-                                    if newpath.ends_with(".exe") {
-                                        buf.alert = Alert::new(
-                                            &[String::from("You shouldn't do that")],
-                                            1_000_000,
-                                        );
-                                        continue;
-                                    }
-                                    buf.buffer_history.hist.push(newpath.clone());
-                                    buf.buffer_history.head = buf.buffer_history.hist.len() - 1;
-                                    buf.filepath = newpath.clone();
-                                    buf.reload_file();
-                                    buf.mode = return_mode;
-                                    if buf.cursor_pos.line >= buf.contents.len() {
-                                        buf.cursor_pos.line = buf.contents.len() - 1;
-                                    }
-                                    if buf.cursor_pos.idx
-                                        > buf.contents[buf.cursor_pos.line].chars().count()
-                                    {
-                                        buf.cursor_pos.idx =
-                                            buf.contents[buf.cursor_pos.line].chars().count();
-                                    }
-                                    buf.update_highlighting();
-                                }
-                            }
-                            _ => {}
-                        },
-                        Mods::CtrlAlt => match key.code {
-                            KeyCode::Char('L') => {
-                                if let Some(showlinenos) = buf.vars.get_mut("line-num-type") {
-                                    if let BimVar::Str(x) = showlinenos {
-                                        *showlinenos = match x.as_str() {
-                                            "absolute" => BimVar::Str("relative".to_string()),
-                                            "relative" => BimVar::Str("none".to_string()),
-                                            _ => BimVar::Str("absolute".to_string()),
-                                        }
-                                    }
-                                }
-                            }
-                            KeyCode::Char('B') => {
-                                if let Some(BimVar::Bool(showbottombar)) =
-                                    buf.vars.get_mut("showbottombar")
-                                {
-                                    *showbottombar = !*showbottombar;
-                                }
-                            }
-                            _ => {}
-                        },
+                        }
+                    }
+                }
+                KeyCode::Char(';') => {
+                    let mut currline = buf.contents[buf.cursor_pos.line].chars();
+                    let mut spaces = 0;
+                    while currline.next() == Some(' ') {
+                        spaces += 1;
+                    }
+                    buf.indent_lvl = spaces / buf.lang.indent_size();
+                    buf.cursor_pos.idx = spaces;
+                }
+                KeyCode::Char(':') => {
+                    let mut currline = buf.contents[buf.cursor_pos.line].chars();
+                    let mut spaces = 0;
+                    while currline.next() == Some(' ') {
+                        spaces += 1;
+                    }
+                    buf.indent_lvl = spaces / buf.lang.indent_size();
+                }
+                KeyCode::Char('/') => {
+                    if buf.mode == Mode::Find {
+                        buf.mode = return_mode;
+                    } else {
+                        buf.mode = Mode::Find;
+                        buf.find_str.clear();
+                    }
+                }
+                KeyCode::Char('n') => {
+                    buf.push_jump();
+                    if buf.move_right() {
+                        'findfwd: loop {
+                            let prevpos = buf.cursor_pos;
+                            if let Some(p) = buf.contents[buf.cursor_pos.line][buf.cursor_pos.idx..]
+                                .find(buf.find_str.as_str())
+                            {
+                                buf.cursor_pos.idx += p;
+                                buf.cursor_pos.idx += buf.find_str.chars().count();
+                                break 'findfwd;
+                            }
+                            buf.cursor_pos.idx = 0;
+                            if !buf.move_down() {
+                                buf.cursor_pos = prevpos;
+                                break 'findfwd;
+                            }
+                        }
+                    }
+                }
+                KeyCode::Char('p') => {
+                    buf.push_jump();
+                    if buf.move_left() {
+                        'findfwd: loop {
+                            let prevpos = buf.cursor_pos;
+                            if let Some(p) = buf.contents[buf.cursor_pos.line]
+                                [0..buf.cursor_pos.idx]
+                                .rfind(buf.find_str.as_str())
+                            {
+                                buf.cursor_pos.idx = p;
+                                buf.cursor_pos.idx += buf.find_str.chars().count();
+                                break 'findfwd;
+                            }
+                            let status = buf.move_up();
+                            buf.cursor_pos.idx = buf.contents[buf.cursor_pos.line].chars().count();
+                            if !status {
+                                buf.cursor_pos = prevpos;
+                                break 'findfwd;
+                            }
+                        }
+                    }
+                }
+                KeyCode::Char('o') => {
+                    buf.newline_below("");
+                }
+                KeyCode::Char('O') => {
+                    if buf.move_up() {
+                        buf.newline_below("");
+                    } else {
+                        buf.contents.insert(0, String::new());
+                        buf.cursor_pos.idx = 0;
+                        buf.update_highlighting();
                     }
                 }
+                KeyCode::Char('x' | 'M') => {
+                    buf.mode = Mode::Switch;
+                    buf.temp_str.clear();
+                }
+                KeyCode::Char('h') => {
+                    buf.contents[buf.cursor_pos.line].replace_range(
+                        (if buf.cursor_pos.idx >= buf.find_str.chars().count() {
+                            buf.cursor_pos.idx - buf.find_str.chars().count()
+                        } else {
+                            0
+                        })..buf.cursor_pos.idx,
+                        &buf.replace_str,
+                    );
+                    if buf.cursor_pos.idx > buf.contents[buf.cursor_pos.line].chars().count() {
+                        buf.cursor_pos.idx = buf.contents[buf.cursor_pos.line].chars().count();
+                    }
+                    buf.update_highlighting();
+                }
+                KeyCode::Char('r') => {
+                    if buf.mode == Mode::ReplaceStr {
+                        buf.mode = return_mode;
+                    } else {
+                        buf.mode = Mode::ReplaceStr;
+                        buf.replace_str.clear();
+                    }
+                }
+                KeyCode::Char('j') => {
+                    if buf.contents.len() > buf.cursor_pos.line + 1 {
+                        let l = buf.contents.remove(buf.cursor_pos.line + 1);
+                        let o = buf.contents[buf.cursor_pos.line].clone();
+                        buf.contents[buf.cursor_pos.line].clear();
+                        buf.contents[buf.cursor_pos.line].push_str(o.trim_end());
+                        buf.contents[buf.cursor_pos.line].push(' ');
+                        buf.contents[buf.cursor_pos.line].push_str(l.trim());
+                    }
+                    buf.update_highlighting();
+                }
+                KeyCode::Char('0') => {
+                    buf.indent_lvl = 0;
+                }
+                KeyCode::Char('k') => {
+                    let indent_size = buf.lang.indent_size();
+                    if buf.indent_lvl * indent_size != buf.cursor_pos.idx {
+                        let killed = buf.contents[buf.cursor_pos.line]
+                            .drain((buf.indent_lvl * indent_size)..buf.cursor_pos.idx)
+                            .collect();
+                        buf.kill(vec![killed]);
+                        buf.cursor_pos.idx = buf.indent_lvl * indent_size;
+                        buf.update_highlighting();
+                    }
+                }
+                KeyCode::Char('K') => {
+                    let killed = buf.contents[buf.cursor_pos.line].split_off(buf.cursor_pos.idx);
+                    buf.kill(vec![killed]);
+                    buf.update_highlighting();
+                }
+                KeyCode::Char('w') => {
+                    while buf.contents[buf.cursor_pos.line]
+                        .chars()
+                        .nth(buf.cursor_pos.idx)
+                        .unwrap_or(' ')
+                        .is_whitespace()
+                    {
+                        if !buf.move_right() {
+                            break;
+                        }
+                    }
+                    while !buf.contents[buf.cursor_pos.line]
+                        .chars()
+                        .nth(buf.cursor_pos.idx)
+                        .unwrap_or(' ')
+                        .is_whitespace()
+                    {
+                        if !buf.move_right() {
+                            break;
+                        }
+                    }
+                    buf.move_right();
+                    if buf.cursor_pos.idx == 0 {
+                        buf.move_left();
+                    }
+                }
+                KeyCode::Char('W') => {
+                    while buf.contents[buf.cursor_pos.line]
+                        .chars()
+                        .nth(buf.cursor_pos.idx)
+                        .unwrap_or(' ')
+                        .is_whitespace()
+                    {
+                        if !buf.move_left() {
+                            break;
+                        }
+                    }
+                    while !buf.contents[buf.cursor_pos.line]
+                        .chars()
+                        .nth(buf.cursor_pos.idx)
+                        .unwrap_or(' ')
+                        .is_whitespace()
+                    {
+                        if !buf.move_left() {
+                            break;
+                        }
+                    }
+                }
+                KeyCode::Char('y') => {
+                    buf.contents.insert(
+                        buf.cursor_pos.line,
+                        buf.contents[buf.cursor_pos.line].clone(),
+                    );
+                    buf.yank(vec![buf.contents[buf.cursor_pos.line].clone()]);
+                    buf.update_highlighting();
+                    buf.move_down();
+                }
+                KeyCode::Char('Y') => {
+                    if buf.mode != Mode::Copy {
+                        buf.mode = Mode::Copy;
+                        buf.temp_str.clear();
+                    }
+                    let numbuf = format!("{} ", buf.cursor_pos.line + 1);
+                    for c in numbuf.chars() {
+                        buf.temp_str.push(c);
+                    }
+                }
+                KeyCode::Char('C') => {
+                    if buf.mode != Mode::KillLines {
+                        buf.mode = Mode::KillLines;
+                        buf.temp_str.clear();
+                    }
+                    let numbuf = format!("{} ", buf.cursor_pos.line + 1);
+                    for c in numbuf.chars() {
+                        buf.temp_str.push(c);
+                    }
+                }
+                KeyCode::Char('A') => {
+                    if buf.cursor_pos.line + 1 < buf.contents.len() {
+                        buf.contents
+                            .swap(buf.cursor_pos.line, buf.cursor_pos.line + 1);
+                        buf.update_highlighting();
+                        buf.move_down();
+                    }
+                }
+                KeyCode::Char('E') => {
+                    if buf.cursor_pos.line != 0 {
+                        buf.contents
+                            .swap(buf.cursor_pos.line, buf.cursor_pos.line - 1);
+                        buf.move_up();
+                        buf.update_highlighting();
+                    }
+                }
+                KeyCode::Char('G') => {
+                    buf.mode = Mode::Goto;
+                    buf.temp_str.clear();
+                }
+                KeyCode::Char('S') => {
+                    buf.snippet_prompt();
+                }
+                KeyCode::Char('[') => {
+                    while buf.cursor_pos.line != 0 && buf.contents[buf.cursor_pos.line].is_empty() {
+                        buf.cursor_pos.line -= 1;
+                    }
+                    while buf.cursor_pos.line != 0 && !buf.contents[buf.cursor_pos.line].is_empty()
+                    {
+                        buf.cursor_pos.line -= 1;
+                    }
+                    if buf.cursor_pos.idx > buf.contents[buf.cursor_pos.line].chars().count() {
+                        buf.cursor_pos.idx = buf.contents[buf.cursor_pos.line].chars().count();
+                    }
+                }
+                KeyCode::Char(']') => {
+                    let content_len = buf.contents.len();
+                    while buf.cursor_pos.line + 1 < content_len
+                        && !buf.contents[buf.cursor_pos.line].is_empty()
+                    {
+                        buf.cursor_pos.line += 1;
+                    }
+                    while buf.cursor_pos.line + 1 < content_len
+                        && buf.contents[buf.cursor_pos.line].is_empty()
+                    {
+                        buf.cursor_pos.line += 1;
+                    }
+                    if buf.cursor_pos.idx > buf.contents[buf.cursor_pos.line].chars().count() {
+                        buf.cursor_pos.idx = buf.contents[buf.cursor_pos.line].chars().count();
+                    }
+                }
+                KeyCode::Char('N') | KeyCode::Char('v') => {
+                    buf.mode = Mode::Nav;
+                    buf.vars
+                        .insert(String::from("ret-to-nav"), BimVar::Bool(true));
+                    buf.temp_str.clear();
+                }
+                KeyCode::Char('-') => {
+                    buf.alert = Alert::new(&[], 1_000_000);
+                }
+                KeyCode::Char('T') => {
+                    buf.mode = Mode::Tee;
+                    buf.replace_str.clear();
+                }
+                KeyCode::Char(x) if x.is_numeric() => {
+                    let (all_matches, mut querylen) = buf.autocomplete.get_candidates(buf);
+                    let matches: Vec<String> =
+                        all_matches.iter().take(5).map(|s| s.to_string()).collect();
+                    match x {
+                        '1' | '2' | '3' | '4' | '5' => {
+                            let replace_str = match x {
+                                '1' => matches.get(0).map_or("", |c| c),
+                                '2' => matches.get(1).map_or("", |c| c),
+                                '3' => matches.get(2).map_or("", |c| c),
+                                '4' => matches.get(3).map_or("", |c| c),
+                                '5' => matches.get(4).map_or("", |c| c),
+                                _ => {
+                                    querylen = 0;
+                                    ""
+                                }
+                            };
+                            if replace_str.is_empty() {
+                                return Flow::Skip;
+                            }
+                            buf.contents[buf.cursor_pos.line].replace_range(
+                                buf.cursor_pos.idx.saturating_sub(querylen)..buf.cursor_pos.idx,
+                                replace_str,
+                            );
+                            buf.cursor_pos.idx += replace_str.chars().count();
+                            buf.cursor_pos.idx -= querylen;
+                            if buf.cursor_pos.idx
+                                > buf.contents[buf.cursor_pos.line].chars().count()
+                            {
+                                buf.cursor_pos.idx =
+                                    buf.contents[buf.cursor_pos.line].chars().count();
+                            }
+                            buf.update_highlighting();
+                        }
+                        '6' => {
+                            let display_matches: Vec<String> = matches
+                                .iter()
+                                .enumerate()
+                                .map(|(i, v)| format!("{}: `{v}`", i + 1))
+                                .collect();
+                            buf.alert = Alert::new(&display_matches, 500_000);
+                        }
+                        _ => {}
+                    }
+                }
+                KeyCode::Char('U') => {
+                    if buf.mode != Mode::Indent {
+                        buf.mode = Mode::Indent;
+                        buf.temp_str.clear();
+                    }
+                    let numbuf = format!("{} ", buf.cursor_pos.line + 1);
+                    for c in numbuf.chars() {
+                        buf.temp_str.push(c);
+                    }
+                }
+                KeyCode::Char('!') => {
+                    if buf.mode == Mode::Command {
+                        buf.mode = return_mode;
+                        buf.temp_str.clear();
+                    } else {
+                        buf.mode = Mode::Command;
+                    }
+                }
+                KeyCode::Char('P') if buf.mode.show_temp() => {
+                    buf.prompt_paste();
+                    buf.picker_idx = 0;
+                }
+                KeyCode::Char('P') => {
+                    buf.paste();
+                }
+                _ => {}
+            },
+            Mods::Ctrl => match key.code {
+                KeyCode::Char('N') => {
+                    buf.mode = Mode::Nav;
+                    buf.vars
+                        .insert(String::from("ret-to-nav"), BimVar::Bool(true));
+                    buf.temp_str.clear();
+                }
+                KeyCode::Char('r') => {
+                    buf.reload_file();
+                    buf.cursor_pos.line = 0;
+                    buf.cursor_pos.idx = 0;
+                    reset_terminal();
+                }
+                KeyCode::Char('z') => {
+                    buf.reload_file();
+                    if buf.cursor_pos.line >= buf.contents.len() {
+                        buf.cursor_pos.line = buf.contents.len() - 1;
+                    }
+                    if buf.cursor_pos.idx > buf.contents[buf.cursor_pos.line].chars().count() {
+                        buf.cursor_pos.idx = buf.contents[buf.cursor_pos.line].chars().count();
+                    }
+                    reset_terminal();
+                }
+                KeyCode::Backspace | KeyCode::Char('w') if buf.mode.show_temp() => {
+                    buf.prompt_delete_word();
+                    buf.picker_idx = 0;
+                }
+                KeyCode::Backspace => {
+                    let mut killed = String::new();
+                    let mut kill_char = |buf: &mut Buffer| {
+                        let c = buf.fast_backspace();
+                        if let Some(c) = c {
+                            killed.insert(0, c);
+                        }
+                        c
+                    };
+                    while kill_char(buf).unwrap_or('a').is_whitespace() {}
+                    let mut last;
+                    'killword: loop {
+                        last = kill_char(buf);
+                        if !last.unwrap_or(' ').is_alphanumeric() {
+                            break 'killword;
+                        }
+                    }
+                    match last {
+                        Some('\n') => buf.update_highlighting(),
+                        Some(c) => {
+                            killed.remove(0);
+                            buf.type_char(c);
+                        }
+                        None => buf.type_char(' '),
+                    }
+                    buf.kill(killed.split('\n').map(String::from).collect());
+                }
+                KeyCode::Char('o') => {
+                    buf.mode = Mode::OpenFile;
+                    buf.temp_str.clear();
+                }
+                KeyCode::Char('y') => {
+                    if buf.top < buf.contents.len() {
+                        buf.top += 1;
+                    }
+                    buf.move_down();
+                }
+                KeyCode::Char('e') => {
+                    if buf.top > 0 {
+                        buf.top -= 1;
+                    }
+                    buf.move_up();
+                }
+                KeyCode::Char('b') => {
+                    let mut ret: Vec<String> = vec![];
+                    for (idx, s) in buf.buffer_history.hist.iter().enumerate() {
+                        if idx == buf.buffer_history.head {
+                            ret.push(format!("> {s}"));
+                        } else {
+                            ret.push(format!("  {s}"));
+                        }
+                    }
+                    buf.alert = Alert::new(&ret, 5_000_000);
+                }
+                KeyCode::Char('p') => {
+                    buf.push_jump();
+                    if buf.buffer_history.head == 0 {
+                        buf.buffer_history.head = buf.buffer_history.hist.len();
+                    }
+                    buf.buffer_history.head -= 1;
+                    buf.save();
+                    buf.filepath = buf.buffer_history.hist[buf.buffer_history.head].clone();
+                    buf.reload_file();
+                    if buf.cursor_pos.line >= buf.contents.len() {
+                        buf.cursor_pos.line = buf.contents.len() - 1;
+                    }
+                    if buf.cursor_pos.idx > buf.contents[buf.cursor_pos.line].chars().count() {
+                        buf.cursor_pos.idx = buf.contents[buf.cursor_pos.line].chars().count();
+                    }
+                    buf.alert = Alert::new(&buf.buffer_history.display(), 500_000);
+                }
+                KeyCode::Char('n') => {
+                    buf.push_jump();
+                    buf.buffer_history.head += 1;
+                    if buf.buffer_history.head >= buf.buffer_history.hist.len() {
+                        buf.buffer_history.head = 0;
+                    }
+                    buf.save();
+                    buf.filepath = buf.buffer_history.hist[buf.buffer_history.head].clone();
+                    buf.reload_file();
+                    if buf.cursor_pos.line >= buf.contents.len() {
+                        buf.cursor_pos.line = buf.contents.len() - 1;
+                    }
+                    if buf.cursor_pos.idx > buf.contents[buf.cursor_pos.line].chars().count() {
+                        buf.cursor_pos.idx = buf.contents[buf.cursor_pos.line].chars().count();
+                    }
+                    let mut ret = vec![];
+                    for (idx, s) in buf.buffer_history.hist.iter().enumerate() {
+                        if idx == buf.buffer_history.head {
+                            ret.push(format!("> {s}"));
+                        } else {
+                            ret.push(format!("  {s}"));
+                        }
+                    }
+                    buf.alert = Alert::new(&ret, 500_000);
+                }
+                KeyCode::Char('d') => {
+                    let prev = buf.filepath.clone();
+                    buf.open_special("*direx");
+                    buf.direx_reveal(&prev);
+                    buf.mode = return_mode;
+                    if buf.cursor_pos.line >= buf.contents.len() {
+                        buf.cursor_pos.line = buf.contents.len() - 1;
+                    }
+                    if buf.cursor_pos.idx > buf.contents[buf.cursor_pos.line].chars().count() {
+                        buf.cursor_pos.idx = buf.contents[buf.cursor_pos.line].chars().count();
+                    }
+                    buf.update_highlighting();
+                }
+                KeyCode::Char(c @ ('a' | 'x' | 'w'))
+                    if matches!(buf.filepath.as_str(), "*search" | "*replace") =>
+                {
+                    buf.replace_ctrl(c);
+                }
+                KeyCode::Char('w') if buf.filepath == "*macros" => {
+                    buf.macros_write();
+                }
+                KeyCode::Char('u') => {
+                    buf.undo_replace();
+                }
+                KeyCode::Char(c @ ('a' | 'v' | 'c' | 'x')) if buf.filepath == "*direx" => {
+                    buf.direx_ctrl(c);
+                }
+                KeyCode::Char('f') => {
+                    if buf.filepath == "*direx" && buf.direx_select() {
+                        buf.mode = return_mode;
+                    }
+                    if buf.filepath == "*search" {
+                        buf.search_select();
+                        buf.mode = return_mode;
+                    }
+                }
+                _ => {}
+            },
+            Mods::CtrlAlt => match key.code {
+                KeyCode::Char('p') => {
+                    buf.paste_cycle();
+                }
+                KeyCode::Char('c') => {
+                    let lines = buf.persistent_buffer.clone();
+                    buf.copy_to_clipboard(&lines);
+                }
+                KeyCode::Char('v') => {
+                    buf.paste_clipboard();
+                }
+                KeyCode::Char('L') => {
+                    if let Some(showlinenos) = buf.vars.get_mut("line-num-type") {
+                        if let BimVar::Str(x) = showlinenos {
+                            *showlinenos = match x.as_str() {
+                                "absolute" => BimVar::Str("relative".to_string()),
+                                "relative" => BimVar::Str("none".to_string()),
+                                _ => BimVar::Str("absolute".to_string()),
+                            }
+                        }
+                    }
+                }
+                KeyCode::Char('B') => {
+                    if let Some(BimVar::Bool(showbottombar)) = buf.vars.get_mut("showbottombar") {
+                        *showbottombar = !*showbottombar;
+                    }
+                }
+                _ => {}
+            },
+        }
+    }
+    Flow::Print
+}
+
+pub fn main() {
+    let mut stdout = io::stdout();
+    let mut args = args().peekable();
+    let mut processing_time: u128 = 0;
+    let mut printing_time: u128 = 0;
+    _ = args.next();
+    let path = if args.peek().is_some() {
+        args.collect::<Vec<String>>().join(" ")
+    } else {
+        String::from("*scratch")
+    };
+    if path.ends_with(".exe") {
+        println!("You shouldn't do this");
+        return;
+    }
+    let mut buf = Buffer::new(&path);
+    reset_terminal();
+    _ = terminal::enable_raw_mode();
+    buf.save();
+    buf.add_tokens();
+    print!("Press any key (ideally esc)...");
+    'ed: loop {
+        let (widthu, heightu) = terminal::size().expect("terminal should have size");
+        let width = widthu as usize;
+        let height = heightu as usize;
+        let event = event::read().expect("there should be an event upon reading");
+        let start = Instant::now();
+        let prev_mode = buf.mode;
+        match &event {
+            Event::Key(key) if key.kind != event::KeyEventKind::Release => {
+                let was_recording = buf.macros.recording.is_some();
+                let flow = handle_key(&mut buf, *key, width, height);
+                buf.record_key(*key, was_recording);
+                buf.track_change(*key, prev_mode);
+                match flow {
+                    Flow::Quit => break 'ed,
+                    Flow::Skip if buf.macros.playback.is_empty() => continue,
+                    _ => {}
+                }
+                after_key(&mut buf, prev_mode);
+                // Macros queued by the key are played back like typed keys.
+                while let Some(keys) = buf.take_playback() {
+                    for k in keys {
+                        let prev_mode = buf.mode;
+                        if let Flow::Quit = handle_key(&mut buf, k, width, height) {
+                            break 'ed;
+                        }
+                        after_key(&mut buf, prev_mode);
+                    }
+                }
+            }
+            Event::Paste(text) => {
+                buf.record_paste(text);
+                buf.paste_event(text);
+                after_key(&mut buf, prev_mode);
             }
+            _ => after_key(&mut buf, prev_mode),
         }
+        buf.update_popup();
         if cfg!(feature = "profile") {
             processing_time += start.elapsed().as_micros();
             processing_time >>= 1;
@@ -1284,5 +1410,6 @@ pub fn main() {
     }
     print!("\x1bc\x1b[?25h");
     buf.save();
+    _ = execute!(io::stdout(), event::DisableBracketedPaste);
     _ = terminal::disable_raw_mode();
 }
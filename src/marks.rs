@@ -0,0 +1,184 @@
+//! Marks and the jump list.
+//!
+//! `m` followed by a letter in nav mode marks the cursor position in the
+//! current file, and `'` followed by the letter jumps back to it. Goto,
+//! searches, file switches and jumps to search results record where the cursor
+//! was in the jump list, which `Alt-Left` and `Alt-Right` move back and forth
+//! through.
+//!
+//! Marks and jumps stay on their lines when lines are inserted or deleted
+//! above them. After every edit of a file with marks, jumps or closed folds,
+//! the contents are compared with the previous ones to find the lines that
+//! changed.
+
+use crate::buffer::{Alert, Buffer, Cursor};
+use std::collections::HashMap;
+
+/// Most jumps kept in the list.
+const JUMPS_LEN: usize = 100;
+
+#[derive(Default)]
+pub struct Marks {
+    /// Marks by file, then name.
+    pub named: HashMap<String, HashMap<char, Cursor>>,
+    /// Positions jumped from, oldest first.
+    pub jumps: Vec<(String, Cursor)>,
+    /// Position in `jumps` while moving through it, or its length otherwise.
+    pub jump_idx: usize,
+    /// File, edit count and contents the positions were last updated for.
+    /// The contents are only kept while the file has positions to update.
+    snapshot: (String, usize, Option<Vec<String>>),
+}
+
+/// Where a line moves to after lines `start..old_end` were replaced by
+/// `start..new_end`. Lines inside the replaced ones stay in the new lines.
+fn shift_line(line: usize, start: usize, old_end: usize, new_end: usize) -> usize {
+    if line < start {
+        line
+    } else if line >= old_end {
+        line + new_end - old_end
+    } else {
+        line.min(new_end.saturating_sub(1)).max(start)
+    }
+}
+
+impl Buffer {
+    /// Whether the current file has marks, jumps or closed folds.
+    fn has_positions(&self) -> bool {
+        self.marks
+            .named
+            .get(&self.filepath)
+            .is_some_and(|m| !m.is_empty())
+            || self.marks.jumps.iter().any(|(p, _)| *p == self.filepath)
+            || self
+                .folds
+                .closed
+                .get(&self.filepath)
+                .is_some_and(|f| !f.is_empty())
+    }
+
+    /// Moves the marks, jumps and folds of the current file along with inserted and deleted lines.
+    pub fn track_lines(&mut self) {
+        let tracked = self.has_positions();
+        let (path, edits, old) = &self.marks.snapshot;
+        let same_file = *path == self.filepath;
+        if same_file && *edits == self.edits && (old.is_some() || !tracked) {
+            return;
+        }
+        if same_file
+            && let Some(old) = old
+            && old.len() != self.contents.len()
+        {
+            let new = &self.contents;
+            let start = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+            let max_end = old.len().min(new.len()) - start;
+            let end = old
+                .iter()
+                .rev()
+                .zip(new.iter().rev())
+                .take(max_end)
+                .take_while(|(a, b)| a == b)
+                .count();
+            let (old_end, new_end) = (old.len() - end, new.len() - end);
+            let shift = |pos: &mut Cursor| pos.line = shift_line(pos.line, start, old_end, new_end);
+            if let Some(marks) = self.marks.named.get_mut(&self.filepath) {
+                marks.values_mut().for_each(shift);
+            }
+            self.marks
+                .jumps
+                .iter_mut()
+                .filter(|(p, _)| *p == self.filepath)
+                .for_each(|(_, pos)| shift(pos));
+            self.shift_folds(start, old_end, new_end);
+        }
+        let contents = tracked.then(|| self.contents.clone());
+        self.marks.snapshot = (self.filepath.clone(), self.edits, contents);
+    }
+
+    /// Marks the cursor position as `name`.
+    pub fn set_mark(&mut self, name: char) {
+        if !name.is_ascii_alphabetic() {
+            return;
+        }
+        self.marks
+            .named
+            .entry(self.filepath.clone())
+            .or_default()
+            .insert(name, self.cursor_pos);
+        self.alert = Alert::new(&[format!("Mark '{name}")], 1_000_000);
+    }
+
+    /// Jumps to the mark `name`.
+    pub fn goto_mark(&mut self, name: char) {
+        let mark = self
+            .marks
+            .named
+            .get(&self.filepath)
+            .and_then(|m| m.get(&name))
+            .copied();
+        match mark {
+            Some(pos) => {
+                self.push_jump();
+                self.cursor_pos = pos;
+                self.clamp_cursor();
+            }
+            None => self.alert = Alert::new(&[format!("No mark '{name}")], 1_000_000),
+        }
+    }
+
+    /// Records the cursor position in the jump list, before jumping away from it.
+    pub fn push_jump(&mut self) {
+        let here = (self.filepath.clone(), self.cursor_pos);
+        let jumps = &mut self.marks.jumps;
+        jumps.truncate(self.marks.jump_idx);
+        if jumps
+            .last()
+            .is_some_and(|(p, c)| *p == here.0 && c.line == here.1.line)
+        {
+            jumps.pop();
+        }
+        jumps.push(here);
+        if jumps.len() > JUMPS_LEN {
+            jumps.remove(0);
+        }
+        self.marks.jump_idx = jumps.len();
+    }
+
+    /// Goes back to the previous position in the jump list.
+    pub fn jump_back(&mut self) {
+        if self.marks.jump_idx == 0 {
+            self.alert = Alert::new(&["No older jumps".to_string()], 1_000_000);
+            return;
+        }
+        if self.marks.jump_idx == self.marks.jumps.len() {
+            // Keep where we are, to come back to it.
+            self.push_jump();
+            self.marks.jump_idx -= 1;
+            if self.marks.jump_idx == 0 {
+                return;
+            }
+        }
+        self.marks.jump_idx -= 1;
+        self.goto_jump();
+    }
+
+    /// Goes forward to the next position in the jump list.
+    pub fn jump_forward(&mut self) {
+        if self.marks.jump_idx + 1 >= self.marks.jumps.len() {
+            self.alert = Alert::new(&["No newer jumps".to_string()], 1_000_000);
+            return;
+        }
+        self.marks.jump_idx += 1;
+        self.goto_jump();
+    }
+
+    fn goto_jump(&mut self) {
+        let (path, pos) = self.marks.jumps[self.marks.jump_idx].clone();
+        if path != self.filepath {
+            self.switch_buffer(&path);
+        }
+        self.cursor_pos = pos;
+        self.clamp_cursor();
+        self.update_highlighting();
+    }
+}
@@ -1,8 +1,14 @@
 use crate::Mods;
 use crate::buffer::*;
+use crate::operator::Op;
+use crate::visual::Select;
 use crossterm::event::{self, KeyCode};
 pub const VIM_ITER_LIMIT: usize = 10000;
 
+pub(crate) fn is_blank(line: &str) -> bool {
+    line.trim().is_empty()
+}
+
 macro_rules! repeat_action {
     ($buf: ident, $action: block) => {
         if $buf.temp_str.is_empty() {
@@ -29,42 +35,18 @@ pub fn handle_nav(
 ) -> bool {
     match modifiers {
         Mods::None => match key.code {
+            code if buf.operator_pending() => {
+                buf.operator_key(code, height);
+            }
+            KeyCode::Char(n)
+                if Op::from_char(n).is_some()
+                    && buf.temp_str.chars().all(|c| c.is_ascii_digit()) =>
+            {
+                buf.temp_str.push(n);
+            }
             KeyCode::Home | KeyCode::Char('0') if buf.temp_str.is_empty() => {
                 buf.cursor_pos.idx = 0;
             }
-            KeyCode::Char(n) if buf.temp_str.starts_with('l') => match n {
-                'l' => {
-                    buf.temp_str.remove(0);
-                    repeat_action!(buf, {
-                        if buf.contents.len() == 1 {
-                            buf.contents[0].clear();
-                            buf.cursor_pos.idx = 0;
-                        } else {
-                            buf.contents.remove(buf.cursor_pos.line);
-                            buf.cursor_pos.idx = 0;
-                        }
-                        let contentlen = buf.contents.len();
-                        if buf.cursor_pos.line >= contentlen && !buf.contents.is_empty() {
-                            buf.cursor_pos.line = contentlen - 1;
-                        }
-                    });
-                    buf.update_highlighting();
-                    buf.temp_str.clear();
-                }
-                'c' => {
-                    buf.temp_str.remove(0);
-                    repeat_action!(buf, {
-                        if buf.move_right() {
-                            buf.backspace();
-                        }
-                    });
-                    buf.temp_str.clear();
-                }
-                d if d.is_numeric() => {
-                    buf.temp_str.push(d);
-                }
-                _ => {}
-            },
             KeyCode::Char(n) if &buf.temp_str == "f" => {
                 buf.temp_str.clear();
                 if buf.move_right() {
@@ -112,34 +94,27 @@ pub fn handle_nav(
                     buf.move_left();
                 }
             }
-            KeyCode::Char(n) if &buf.temp_str == "x" => {
-                let did_anything = match n {
-                    'c' => {
-                        if buf.move_right() {
-                            buf.backspace();
-                        }
-                        true
-                    }
-                    'w' => {
-                        'del: loop {
-                            if buf.move_right() {
-                                if buf.backspace().unwrap_or(' ').is_whitespace() {
-                                    break 'del;
-                                }
-                            } else {
-                                break 'del;
-                            }
-                        }
-                        true
-                    }
-                    _ => false,
-                };
-                if did_anything {
-                    buf.mode = Mode::Default;
-                    buf.vars
-                        .insert(String::from("ret-to-nav"), BimVar::Bool(false));
-                }
+            KeyCode::Char(n) if &buf.temp_str == "Q" => {
                 buf.temp_str.clear();
+                buf.start_recording(n);
+            }
+            KeyCode::Char(n) if buf.temp_str.ends_with('@') => {
+                buf.temp_str.pop();
+                repeat_action!(buf, {
+                    buf.play_macro(n);
+                });
+            }
+            KeyCode::Char(n) if &buf.temp_str == "m" => {
+                buf.temp_str.clear();
+                buf.set_mark(n);
+            }
+            KeyCode::Char(n) if &buf.temp_str == "'" => {
+                buf.temp_str.clear();
+                buf.goto_mark(n);
+            }
+            KeyCode::Char(n) if &buf.temp_str == "\"" => {
+                buf.temp_str.clear();
+                buf.select_register(n);
             }
             KeyCode::Char(n) if n.is_numeric() => {
                 buf.temp_str.push(n);
@@ -179,11 +154,31 @@ pub fn handle_nav(
             KeyCode::Char('r') => {
                 buf.temp_str.push('r');
             }
-            KeyCode::Char('x') => {
-                buf.temp_str.push('x');
+            KeyCode::Char('Q') if buf.macros.recording.is_some() => {
+                buf.stop_recording();
+            }
+            KeyCode::Char('Q') => {
+                buf.temp_str = String::from("Q");
             }
-            KeyCode::Char('l') => {
-                buf.temp_str.push('l');
+            KeyCode::Char('@') => {
+                buf.temp_str.push('@');
+            }
+            KeyCode::Char('"') => {
+                buf.temp_str = String::from("\"");
+            }
+            KeyCode::Char('m') => {
+                buf.temp_str = String::from("m");
+            }
+            KeyCode::Char('\'') => {
+                buf.temp_str = String::from("'");
+            }
+            KeyCode::Char('.') => {
+                let count = buf.temp_str.parse().ok();
+                buf.temp_str.clear();
+                buf.repeat_change(count);
+            }
+            KeyCode::Char('P') => {
+                buf.paste();
             }
             KeyCode::Char('h') => {
                 buf.contents[buf.cursor_pos.line].replace_range(
@@ -269,6 +264,12 @@ pub fn handle_nav(
                     }
                 });
             }
+            KeyCode::Char('v') => {
+                buf.visual(Select::Char);
+            }
+            KeyCode::Char('V') => {
+                buf.visual(Select::Line);
+            }
             KeyCode::Char('g') => {
                 buf.mode = Mode::Goto;
                 buf.temp_str.clear();
@@ -294,6 +295,46 @@ pub fn handle_nav(
                 buf.indent_lvl = spaces / buf.lang.indent_size();
                 buf.cursor_pos.idx = spaces;
             }
+            KeyCode::Char(']') => {
+                repeat_action!(buf, {
+                    let last = buf.contents.len() - 1;
+                    let mut line = buf.cursor_pos.line;
+                    while line < last && is_blank(&buf.contents[line]) {
+                        line += 1;
+                    }
+                    while line < last && !is_blank(&buf.contents[line]) {
+                        line += 1;
+                    }
+                    buf.cursor_pos.line = line;
+                    buf.cursor_pos.idx = if is_blank(&buf.contents[line]) {
+                        0
+                    } else {
+                        buf.contents[line].chars().count()
+                    };
+                });
+            }
+            KeyCode::Char('[') => {
+                repeat_action!(buf, {
+                    let mut line = buf.cursor_pos.line;
+                    while line > 0 && is_blank(&buf.contents[line]) {
+                        line -= 1;
+                    }
+                    while line > 0 && !is_blank(&buf.contents[line]) {
+                        line -= 1;
+                    }
+                    buf.cursor_pos.line = line;
+                    buf.cursor_pos.idx = 0;
+                });
+            }
+            KeyCode::Char('%') => {
+                buf.jump_to_bracket();
+            }
+            KeyCode::Char('z') => {
+                buf.toggle_fold();
+            }
+            KeyCode::Char('Z') => {
+                buf.toggle_all_folds();
+            }
             KeyCode::Char('b') => {
                 let linect = buf.contents.len();
                 buf.cursor_pos.line = if linect == 0 { 0 } else { linect - 1 };
@@ -320,6 +361,7 @@ pub fn handle_nav(
                 });
             }
             KeyCode::Char('n') => {
+                buf.push_jump();
                 repeat_action!(buf, {
                     if buf.move_right() {
                         'findfwd: loop {
@@ -341,6 +383,7 @@ pub fn handle_nav(
                 });
             }
             KeyCode::Char('p') => {
+                buf.push_jump();
                 repeat_action!(buf, {
                     if buf.move_left() {
                         'findfwd: loop {
@@ -380,12 +423,13 @@ pub fn handle_nav(
                 });
             }
             KeyCode::Char('k') => {
+                let mut killed = vec![];
                 repeat_action!(buf, {
                     if buf.contents.len() == 1 {
-                        buf.contents[0].clear();
+                        killed.push(std::mem::take(&mut buf.contents[0]));
                         buf.cursor_pos.idx = 0;
                     } else {
-                        buf.contents.remove(buf.cursor_pos.line);
+                        killed.push(buf.contents.remove(buf.cursor_pos.line));
                         buf.cursor_pos.idx = 0;
                     }
                     let contentlen = buf.contents.len();
@@ -393,15 +437,17 @@ pub fn handle_nav(
                         buf.cursor_pos.line = contentlen - 1;
                     }
                 });
+                buf.kill(killed);
                 buf.update_highlighting();
             }
             KeyCode::Char('K') => {
+                let mut killed = vec![];
                 repeat_action!(buf, {
                     if buf.contents.len() == 1 {
-                        buf.contents[0].clear();
+                        killed.push(std::mem::take(&mut buf.contents[0]));
                         buf.cursor_pos.idx = 0;
                     } else {
-                        buf.contents.remove(buf.cursor_pos.line);
+                        killed.push(buf.contents.remove(buf.cursor_pos.line));
                         buf.cursor_pos.idx = 0;
                     }
                     let contentlen = buf.contents.len();
@@ -409,6 +455,7 @@ pub fn handle_nav(
                         buf.cursor_pos.line = contentlen - 1;
                     }
                 });
+                buf.kill(killed);
                 buf.mode = Mode::Default;
                 buf.move_up();
                 buf.newline_below("");
@@ -476,9 +523,27 @@ pub fn handle_nav(
             KeyCode::Char('_') => {
                 buf.temp_str.clear();
             }
+            KeyCode::Tab => {
+                buf.next_tab_stop();
+            }
+            KeyCode::BackTab => {
+                buf.prev_tab_stop();
+            }
+            KeyCode::Enter if buf.filepath == "*direx" => {
+                buf.direx_select();
+            }
+            KeyCode::Enter if buf.filepath == "*search" => {
+                buf.search_select();
+            }
             _ => {}
         },
         Mods::Alt => match key.code {
+            KeyCode::Left => {
+                buf.jump_back();
+            }
+            KeyCode::Right => {
+                buf.jump_forward();
+            }
             KeyCode::Char('q') => {
                 return true;
             }
@@ -486,6 +551,19 @@ pub fn handle_nav(
                 buf.mode = Mode::OpenFile;
                 buf.temp_str.clear();
             }
+            KeyCode::Char('f') => {
+                buf.find_file();
+            }
+            KeyCode::Char('m') => {
+                buf.open_special("*macros");
+                buf.cursor_pos.line = 0;
+                buf.cursor_pos.idx = 0;
+                buf.update_highlighting();
+            }
+            KeyCode::Char('g') => {
+                buf.mode = Mode::Grep;
+                buf.temp_str.clear();
+            }
             KeyCode::Char('a') => {
                 repeat_action!(buf, {
                     if buf.top < buf.contents.len() {
@@ -494,9 +572,11 @@ pub fn handle_nav(
                     buf.move_down();
                 });
             }
+            KeyCode::Char('p') => {
+                buf.paste_cycle();
+            }
             KeyCode::Char('s') => {
-                buf.mode = Mode::Snippet;
-                buf.temp_str.clear();
+                buf.snippet_prompt();
             }
             KeyCode::Char('y') => {
                 if buf.mode != Mode::Copy {
@@ -518,7 +598,30 @@ pub fn handle_nav(
             }
             _ => {}
         },
-        Mods::Ctrl => {}
+        Mods::Ctrl => match key.code {
+            KeyCode::Char('v') => {
+                buf.visual(Select::Block);
+            }
+            KeyCode::Char('f') if buf.filepath == "*direx" => {
+                buf.direx_select();
+            }
+            KeyCode::Char('f') if buf.filepath == "*search" => {
+                buf.search_select();
+            }
+            KeyCode::Char('w') if buf.filepath == "*macros" => {
+                buf.macros_write();
+            }
+            KeyCode::Char(c) if buf.filepath == "*direx" => {
+                buf.direx_ctrl(c);
+            }
+            KeyCode::Char(c) if matches!(buf.filepath.as_str(), "*search" | "*replace") => {
+                buf.replace_ctrl(c);
+            }
+            KeyCode::Char('u') => {
+                buf.undo_replace();
+            }
+            _ => {}
+        },
         Mods::CtrlAlt => {}
     }
     false
@@ -0,0 +1,505 @@
+//! Operators and motions in nav mode.
+//!
+//! An operator key followed by a motion acts on the text the motion moves
+//! over, like `lw` to delete a word or `Y3a` to copy four lines. Typing the
+//! operator key twice acts on whole lines, and `c` acts on the chars at the
+//! cursor. Counts can be typed before the operator and before the motion, and
+//! they multiply. `I` (inner) or `A` (around) followed by a char selects a
+//! text object instead of moving: a word, quotes, brackets or a paragraph.
+//!
+//! The pending command is kept in `temp_str`, as in `2l3` or `xAf`.
+
+use crate::Mods;
+use crate::buffer::{BimVar, Buffer, Cursor, Mode};
+use crate::nav::{VIM_ITER_LIMIT, handle_nav, is_blank};
+use crate::visual::toggle_case;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Op {
+    Delete,
+    Change,
+    Yank,
+    Indent,
+    Dedent,
+    Case,
+}
+
+impl Op {
+    pub fn from_char(c: char) -> Option<Op> {
+        match c {
+            'l' => Some(Op::Delete),
+            'x' => Some(Op::Change),
+            'Y' => Some(Op::Yank),
+            '+' => Some(Op::Indent),
+            '-' => Some(Op::Dedent),
+            '~' => Some(Op::Case),
+            _ => None,
+        }
+    }
+}
+
+/// An operator being typed, parsed from `temp_str`.
+struct Pending {
+    op: Op,
+    key: char,
+    /// Product of the counts, if any was typed.
+    count: Option<usize>,
+    /// Whether a count follows the operator.
+    motion_count: bool,
+    /// `f`, `F`, `I` or `A` waiting for a char.
+    suffix: Option<char>,
+}
+
+fn parse_pending(s: &str) -> Option<Pending> {
+    let digits = |s: &str| s.chars().take_while(|c| c.is_ascii_digit()).count();
+    let n = digits(s);
+    let first = &s[..n];
+    let mut rest = s[n..].chars();
+    let key = rest.next()?;
+    let op = Op::from_char(key)?;
+    let rest = rest.as_str();
+    let n = digits(rest);
+    let second = &rest[..n];
+    let mut rest = rest[n..].chars();
+    let suffix = rest.next();
+    if rest.next().is_some() || suffix.is_some_and(|c| !matches!(c, 'f' | 'F' | 'I' | 'A')) {
+        return None;
+    }
+    let count = match (first.parse::<usize>().ok(), second.parse::<usize>().ok()) {
+        (None, None) => None,
+        (a, b) => Some(a.unwrap_or(1).saturating_mul(b.unwrap_or(1))),
+    };
+    Some(Pending {
+        op,
+        key,
+        count,
+        motion_count: !second.is_empty(),
+        suffix,
+    })
+}
+
+/// Text an operator acts on: from `start` up to `end`, or lines `start` to
+/// `end` if `lines` is set.
+struct Span {
+    start: Cursor,
+    end: Cursor,
+    lines: bool,
+}
+
+/// Kind of char, for finding the bounds of words.
+fn class(c: char) -> u8 {
+    if c.is_whitespace() {
+        0
+    } else if c.is_alphanumeric() || c == '_' {
+        1
+    } else {
+        2
+    }
+}
+
+impl Buffer {
+    /// Whether an operator is waiting for its motion.
+    pub fn operator_pending(&self) -> bool {
+        parse_pending(&self.temp_str).is_some()
+    }
+
+    /// Handles a key typed after an operator.
+    pub fn operator_key(&mut self, code: KeyCode, height: usize) {
+        let Some(p) = parse_pending(&self.temp_str) else {
+            return;
+        };
+        let count = p.count.unwrap_or(1).min(VIM_ITER_LIMIT);
+        let c = match code {
+            KeyCode::Char(c) => c,
+            KeyCode::Home => '0',
+            KeyCode::End => '$',
+            _ => {
+                self.temp_str.clear();
+                return;
+            }
+        };
+        let span = match p.suffix {
+            Some(s @ ('f' | 'F')) => self.motion_span(KeyCode::Char(c), Some(s), count, height),
+            Some(s) => self.object_span(c, s == 'A', count),
+            None => match c {
+                '1'..='9' => {
+                    self.temp_str.push(c);
+                    return;
+                }
+                '0' if p.motion_count => {
+                    self.temp_str.push(c);
+                    return;
+                }
+                'f' | 'F' | 'I' | 'A' => {
+                    self.temp_str.push(c);
+                    return;
+                }
+                c if c == p.key => {
+                    let line = self.cursor_pos.line;
+                    let last = (line + count - 1).min(self.contents.len() - 1);
+                    Some(Span {
+                        start: Cursor { line, idx: 0 },
+                        end: Cursor { line: last, idx: 0 },
+                        lines: true,
+                    })
+                }
+                // The chars at the cursor, like moving right over them.
+                'c' => self.motion_span(KeyCode::Char('i'), None, count, height),
+                'i' | 'e' | 'a' | 'w' | 'W' | 't' | 'b' | 'u' | 'd' | '0' | '$' | ';' | 'n'
+                | 'p' | '[' | ']' | '%' => self.motion_span(KeyCode::Char(c), None, count, height),
+                _ => None,
+            },
+        };
+        self.temp_str.clear();
+        if let Some(span) = span {
+            self.apply_operator(p.op, span);
+        }
+    }
+
+    /// Span moved over by a nav motion, repeated `count` times.
+    fn motion_span(
+        &mut self,
+        code: KeyCode,
+        prefix: Option<char>,
+        count: usize,
+        height: usize,
+    ) -> Option<Span> {
+        let start = self.cursor_pos;
+        let key = KeyEvent::new(code, KeyModifiers::NONE);
+        for _ in 0..count {
+            self.temp_str = prefix.map(String::from).unwrap_or_default();
+            handle_nav(self, key, &Mods::None, height, 0);
+        }
+        self.temp_str.clear();
+        let end = self.cursor_pos;
+        self.cursor_pos = start;
+        let lines =
+            prefix.is_none() && matches!(code, KeyCode::Char('e' | 'a' | 't' | 'b' | 'u' | 'd'));
+        let (start, end) = if (end.line, end.idx) < (start.line, start.idx) {
+            (end, start)
+        } else {
+            (start, end)
+        };
+        if !lines && start == end {
+            return None;
+        }
+        // The bracket at the end is acted on too, unless the cursor was just
+        // after a closing bracket and is the end itself.
+        let end = if code == KeyCode::Char('%') && self.bracket_at(end).is_some() {
+            Cursor {
+                idx: end.idx + 1,
+                ..end
+            }
+        } else {
+            end
+        };
+        Some(Span { start, end, lines })
+    }
+
+    fn char_at(&self, pos: Cursor) -> Option<char> {
+        self.contents[pos.line].chars().nth(pos.idx)
+    }
+
+    fn next_pos(&self, pos: Cursor) -> Option<Cursor> {
+        if pos.idx + 1 < self.contents[pos.line].chars().count() {
+            Some(Cursor {
+                line: pos.line,
+                idx: pos.idx + 1,
+            })
+        } else if pos.line + 1 < self.contents.len() {
+            Some(Cursor {
+                line: pos.line + 1,
+                idx: 0,
+            })
+        } else {
+            None
+        }
+    }
+
+    fn prev_pos(&self, pos: Cursor) -> Option<Cursor> {
+        if pos.idx > 0 {
+            Some(Cursor {
+                line: pos.line,
+                idx: pos.idx - 1,
+            })
+        } else if pos.line > 0 {
+            let idx = self.contents[pos.line - 1]
+                .chars()
+                .count()
+                .saturating_sub(1);
+            Some(Cursor {
+                line: pos.line - 1,
+                idx,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Finds the unclosed `open` at or before `from`.
+    fn find_open(&self, from: Cursor, open: char, close: char) -> Option<Cursor> {
+        let mut depth = 0;
+        let mut pos = Some(from);
+        while let Some(p) = pos {
+            match self.char_at(p) {
+                Some(c) if c == close && p != from => depth += 1,
+                Some(c) if c == open => {
+                    if depth == 0 {
+                        return Some(p);
+                    }
+                    depth -= 1;
+                }
+                _ => {}
+            }
+            pos = self.prev_pos(p);
+        }
+        None
+    }
+
+    /// Finds the `close` matching the `open` at `from`.
+    fn find_close(&self, from: Cursor, open: char, close: char) -> Option<Cursor> {
+        let mut depth = 0;
+        let mut pos = self.next_pos(from);
+        while let Some(p) = pos {
+            match self.char_at(p) {
+                Some(c) if c == open => depth += 1,
+                Some(c) if c == close => {
+                    if depth == 0 {
+                        return Some(p);
+                    }
+                    depth -= 1;
+                }
+                _ => {}
+            }
+            pos = self.next_pos(p);
+        }
+        None
+    }
+
+    /// Span of the text object `c`. The count picks outer brackets.
+    fn object_span(&self, c: char, around: bool, count: usize) -> Option<Span> {
+        let Cursor { line, idx } = self.cursor_pos;
+        let chars: Vec<char> = self.contents[line].chars().collect();
+        let at = |idx| Cursor { line, idx };
+        match c {
+            'w' => {
+                if chars.is_empty() {
+                    return None;
+                }
+                let idx = idx.min(chars.len() - 1);
+                let cls = class(chars[idx]);
+                let mut a = idx;
+                while a > 0 && class(chars[a - 1]) == cls {
+                    a -= 1;
+                }
+                let mut b = idx + 1;
+                while b < chars.len() && class(chars[b]) == cls {
+                    b += 1;
+                }
+                if around && cls != 0 {
+                    let trailing = chars[b..].iter().take_while(|c| c.is_whitespace()).count();
+                    if trailing > 0 {
+                        b += trailing;
+                    } else {
+                        while a > 0 && chars[a - 1].is_whitespace() {
+                            a -= 1;
+                        }
+                    }
+                }
+                Some(Span {
+                    start: at(a),
+                    end: at(b),
+                    lines: false,
+                })
+            }
+            '"' | '\'' | '`' => {
+                let quotes: Vec<usize> = (0..chars.len()).filter(|&i| chars[i] == c).collect();
+                let (a, b) = quotes
+                    .chunks_exact(2)
+                    .map(|q| (q[0], q[1]))
+                    .find(|&(_, b)| b >= idx)?;
+                Some(if around {
+                    Span {
+                        start: at(a),
+                        end: at(b + 1),
+                        lines: false,
+                    }
+                } else {
+                    Span {
+                        start: at(a + 1),
+                        end: at(b),
+                        lines: false,
+                    }
+                })
+            }
+            '(' | ')' | '[' | ']' | '{' | '}' | '<' | '>' => {
+                let (open, close) = match c {
+                    '(' | ')' => ('(', ')'),
+                    '[' | ']' => ('[', ']'),
+                    '{' | '}' => ('{', '}'),
+                    _ => ('<', '>'),
+                };
+                let mut from = at(idx.min(chars.len().saturating_sub(1)));
+                let mut start = self.find_open(from, open, close)?;
+                for _ in 1..count {
+                    from = self.prev_pos(start)?;
+                    start = self.find_open(from, open, close)?;
+                }
+                let end = self.find_close(start, open, close)?;
+                Some(if around {
+                    Span {
+                        start,
+                        end: Cursor {
+                            line: end.line,
+                            idx: end.idx + 1,
+                        },
+                        lines: false,
+                    }
+                } else {
+                    Span {
+                        start: Cursor {
+                            line: start.line,
+                            idx: start.idx + 1,
+                        },
+                        end,
+                        lines: false,
+                    }
+                })
+            }
+            'p' => {
+                let blank = is_blank(&self.contents[line]);
+                let same = |l: usize| is_blank(&self.contents[l]) == blank;
+                let mut first = line;
+                while first > 0 && same(first - 1) {
+                    first -= 1;
+                }
+                let mut last = line;
+                while last + 1 < self.contents.len() && same(last + 1) {
+                    last += 1;
+                }
+                if around {
+                    let other = |l: usize| is_blank(&self.contents[l]) != blank;
+                    if last + 1 < self.contents.len() {
+                        while last + 1 < self.contents.len() && other(last + 1) {
+                            last += 1;
+                        }
+                    } else {
+                        while first > 0 && other(first - 1) {
+                            first -= 1;
+                        }
+                    }
+                }
+                Some(Span {
+                    start: Cursor {
+                        line: first,
+                        idx: 0,
+                    },
+                    end: Cursor { line: last, idx: 0 },
+                    lines: true,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// The text of a span, as lines.
+    fn span_text(&self, span: &Span) -> Vec<String> {
+        let (start, end) = (span.start, span.end);
+        if span.lines {
+            return self.contents[start.line..=end.line].to_vec();
+        }
+        (start.line..=end.line)
+            .map(|l| {
+                let a = if l == start.line { start.idx } else { 0 };
+                let line = self.contents[l].chars().skip(a);
+                if l == end.line {
+                    line.take(end.idx.saturating_sub(a)).collect()
+                } else {
+                    line.collect()
+                }
+            })
+            .collect()
+    }
+
+    fn delete_span(&mut self, span: &Span) {
+        let (start, end) = (span.start, span.end);
+        if span.lines {
+            self.contents.drain(start.line..=end.line);
+            if self.contents.is_empty() {
+                self.contents.push(String::new());
+            }
+            self.cursor_pos = Cursor {
+                line: start.line.min(self.contents.len() - 1),
+                idx: 0,
+            };
+            return;
+        }
+        let mut new: String = self.contents[start.line].chars().take(start.idx).collect();
+        new.extend(self.contents[end.line].chars().skip(end.idx));
+        self.contents.splice(start.line..=end.line, [new]);
+        self.cursor_pos = start;
+    }
+
+    fn apply_operator(&mut self, op: Op, span: Span) {
+        let (start, end) = (span.start, span.end);
+        match op {
+            Op::Delete => {
+                self.kill(self.span_text(&span));
+                self.delete_span(&span);
+            }
+            Op::Change => {
+                self.kill(self.span_text(&span));
+                self.delete_span(&span);
+                if span.lines {
+                    self.contents.insert(start.line, String::new());
+                    self.cursor_pos = Cursor {
+                        line: start.line,
+                        idx: 0,
+                    };
+                }
+                self.mode = Mode::Default;
+                self.vars
+                    .insert(String::from("ret-to-nav"), BimVar::Bool(false));
+            }
+            Op::Yank => {
+                self.yank(self.span_text(&span));
+                if !span.lines {
+                    self.cursor_pos = start;
+                }
+            }
+            Op::Indent | Op::Dedent => {
+                // A motion to the start of a line leaves that line out.
+                let last = if !span.lines && end.idx == 0 && end.line > start.line {
+                    end.line - 1
+                } else {
+                    end.line
+                };
+                self.indent_lines(start.line, last, op == Op::Dedent);
+            }
+            Op::Case => {
+                let text = self.span_text(&span);
+                for (i, part) in text.iter().enumerate() {
+                    let l = start.line + i;
+                    let a = if span.lines || l != start.line {
+                        0
+                    } else {
+                        start.idx
+                    };
+                    let mut chars: Vec<char> = self.contents[l].chars().collect();
+                    let b = (a + part.chars().count()).min(chars.len());
+                    let a = a.min(b);
+                    chars.splice(a..b, toggle_case(part).chars());
+                    self.contents[l] = chars.into_iter().collect();
+                }
+                if !span.lines {
+                    self.cursor_pos = start;
+                }
+            }
+        }
+        self.cursor_pos.line = self.cursor_pos.line.min(self.contents.len() - 1);
+        let len = self.contents[self.cursor_pos.line].chars().count();
+        self.cursor_pos.idx = self.cursor_pos.idx.min(len);
+        self.update_highlighting();
+    }
+}
@@ -0,0 +1,161 @@
+//! Paths typed in prompts: expansion and completion.
+
+use crate::buffer::{Alert, Buffer, Mode};
+use crate::languages::StyledChar;
+use std::{env, fs, path::Path};
+
+/// Expands a leading `~` to the home directory and `$VAR` or `${VAR}` to
+/// environment variables. Unknown variables are left as they are.
+pub fn expand(input: &str) -> String {
+    let home = if cfg!(target_os = "windows") {
+        env::var("USERPROFILE")
+    } else {
+        env::var("HOME")
+    };
+    let mut ret = String::new();
+    let mut rest = input;
+    if let Ok(home) = home
+        && (rest == "~" || rest.starts_with("~/") || rest.starts_with("~\\"))
+    {
+        ret.push_str(&home);
+        rest = &rest[1..];
+    }
+    while let Some(i) = rest.find('$') {
+        ret.push_str(&rest[..i]);
+        let after = &rest[i + 1..];
+        let (name, len) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            },
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+        match env::var(name) {
+            Ok(value) if !name.is_empty() => ret.push_str(&value),
+            _ => ret.push_str(&rest[i..i + 1 + len]),
+        }
+        rest = &after[len..];
+    }
+    ret.push_str(rest);
+    ret
+}
+
+/// Splits a path into the directory part, including the separator, and the name being typed.
+fn split(input: &str) -> (&str, &str) {
+    match input.rfind(['/', '\\']) {
+        Some(i) => input.split_at(i + 1),
+        None => ("", input),
+    }
+}
+
+/// Names in the directory of `input` that start with the name being typed.
+/// Directories end with `/`. Hidden entries are only listed when the name starts with `.`.
+pub fn completions(input: &str) -> Vec<String> {
+    let (dir, name) = split(input);
+    let Ok(entries) = fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+        return vec![];
+    };
+    let mut ret: Vec<(bool, String)> = entries
+        .flatten()
+        .filter_map(|e| {
+            let n = e.file_name().to_string_lossy().to_string();
+            if !n.starts_with(name) || (n.starts_with('.') && !name.starts_with('.')) {
+                return None;
+            }
+            let is_dir = e.path().is_dir();
+            Some((is_dir, if is_dir { format!("{n}/") } else { n }))
+        })
+        .collect();
+    ret.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    ret.into_iter().map(|(_, n)| n).collect()
+}
+
+fn common_prefix(names: &[String]) -> &str {
+    let Some(first) = names.first() else {
+        return "";
+    };
+    let mut len = first.len();
+    for n in &names[1..] {
+        len = first
+            .char_indices()
+            .zip(n.chars())
+            .take_while(|((_, a), b)| a == b)
+            .last()
+            .map_or(0, |((i, a), _)| i + a.len_utf8())
+            .min(len);
+    }
+    &first[..len]
+}
+
+/// Renders completions as popup lines.
+pub fn render(names: &[String]) -> Vec<Vec<StyledChar>> {
+    const MAX: usize = 8;
+    let mut ret: Vec<Vec<StyledChar>> = names
+        .iter()
+        .take(MAX)
+        .map(|n| {
+            let style = if n.ends_with('/') { "\x1b[34m" } else { "" };
+            StyledChar::colour_string(&format!("  {n}"), style)
+        })
+        .collect();
+    if names.len() > MAX {
+        ret.push(StyledChar::colour_string(
+            &format!("  ... and {} more", names.len() - MAX),
+            "\x1b[2m",
+        ));
+    }
+    ret
+}
+
+impl Buffer {
+    /// Completes the path in `temp_str` as far as the completions agree.
+    pub fn complete_path(&mut self) {
+        let input = expand(&self.temp_str);
+        let names = completions(&input);
+        let (dir, name) = split(&input);
+        let prefix = common_prefix(&names);
+        if prefix.len() > name.len() {
+            self.temp_str = format!("{dir}{prefix}");
+        } else {
+            self.temp_str = input;
+        }
+        self.prompt_cursor = None;
+    }
+
+    /// Opens the path in `temp_str`, asking for confirmation if it doesn't exist.
+    /// Directories are completed instead.
+    pub fn open_prompt(&mut self, return_mode: Mode) {
+        let path = expand(self.temp_str.trim());
+        if path.is_empty() {
+            self.mode = return_mode;
+            return;
+        }
+        if path.ends_with(".exe") {
+            self.alert = Alert::new(&[String::from("You shouldn't do that")], 1_000_000);
+            return;
+        }
+        if Path::new(&path).is_dir() {
+            self.temp_str = if path.ends_with(['/', '\\']) {
+                path
+            } else {
+                format!("{path}/")
+            };
+            self.prompt_cursor = None;
+            return;
+        }
+        self.temp_str.clear();
+        if Path::new(&path).exists() {
+            self.open_file(&path);
+            self.mode = return_mode;
+        } else {
+            self.alert = Alert::new(&[format!("Create {path}? (y/n)")], 3_000_000);
+            self.create_path = Some(path);
+            self.mode = Mode::Confirm;
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! Line editing of `temp_str` in the prompt modes.
+//!
+//! The prompt has a cursor that moves with Left, Right, Home and End, and
+//! every prompt mode keeps a history of what was entered, recalled with Up
+//! and Down. The `Command` history is kept in the `history` file of the user
+//! configuration directory.
+
+use crate::buffer::{Buffer, Mode};
+use crate::config;
+use std::fs;
+
+/// Most entries kept in the history of a mode.
+const HISTORY_LEN: usize = 100;
+
+/// Loads the saved `Command` history.
+pub fn load_history() -> Vec<String> {
+    config::user_dir()
+        .and_then(|d| fs::read_to_string(d.join("history")).ok())
+        .map(|s| s.lines().map(|l| l.to_string()).collect())
+        .unwrap_or_default()
+}
+
+fn save_history(hist: &[String]) {
+    if let Some(dir) = config::user_dir() {
+        _ = fs::create_dir_all(&dir);
+        _ = fs::write(dir.join("history"), hist.join("\n") + "\n");
+    }
+}
+
+/// Byte index of the char at `idx` in `s`.
+fn byte_idx(s: &str, idx: usize) -> usize {
+    s.char_indices().nth(idx).map_or(s.len(), |(i, _)| i)
+}
+
+impl Buffer {
+    /// Char index of the prompt cursor.
+    pub fn prompt_idx(&self) -> usize {
+        let len = self.temp_str.chars().count();
+        self.prompt_cursor.map_or(len, |i| i.min(len))
+    }
+
+    /// Forgets the prompt cursor and history position, after the mode changes.
+    /// A file waiting to be created is forgotten too once the confirmation is left.
+    pub fn reset_prompt(&mut self) {
+        self.prompt_cursor = None;
+        self.history_idx = None;
+        if self.mode != Mode::Confirm {
+            self.create_path = None;
+        }
+    }
+
+    pub fn prompt_insert(&mut self, c: char) {
+        let idx = self.prompt_idx();
+        self.temp_str.insert(byte_idx(&self.temp_str, idx), c);
+        self.prompt_cursor = Some(idx + 1);
+    }
+
+    pub fn prompt_backspace(&mut self) {
+        let idx = self.prompt_idx();
+        if idx > 0 {
+            self.temp_str.remove(byte_idx(&self.temp_str, idx - 1));
+            self.prompt_cursor = Some(idx - 1);
+        }
+    }
+
+    pub fn prompt_delete(&mut self) {
+        let idx = self.prompt_idx();
+        if idx < self.temp_str.chars().count() {
+            self.temp_str.remove(byte_idx(&self.temp_str, idx));
+        }
+    }
+
+    /// Deletes the word before the cursor, and the whitespace after it.
+    pub fn prompt_delete_word(&mut self) {
+        let chars: Vec<char> = self.temp_str.chars().collect();
+        let end = self.prompt_idx();
+        let mut start = end;
+        while start > 0 && chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        while start > 0 && chars[start - 1].is_alphanumeric() {
+            start -= 1;
+        }
+        if start == end && start > 0 {
+            start -= 1;
+        }
+        let (s, e) = (
+            byte_idx(&self.temp_str, start),
+            byte_idx(&self.temp_str, end),
+        );
+        self.temp_str.replace_range(s..e, "");
+        self.prompt_cursor = Some(start);
+    }
+
+    pub fn prompt_left(&mut self) {
+        self.prompt_cursor = Some(self.prompt_idx().saturating_sub(1));
+    }
+
+    pub fn prompt_right(&mut self) {
+        self.prompt_cursor = Some(self.prompt_idx() + 1);
+    }
+
+    pub fn prompt_home(&mut self) {
+        self.prompt_cursor = Some(0);
+    }
+
+    pub fn prompt_end(&mut self) {
+        self.prompt_cursor = None;
+    }
+
+    /// Inserts the first line of `persistent_buffer` at the cursor.
+    pub fn prompt_paste(&mut self) {
+        let Some(line) = self.persistent_buffer.first() else {
+            return;
+        };
+        let idx = self.prompt_idx();
+        let line = line.trim_end_matches(['\r', '\n']).to_string();
+        self.temp_str
+            .insert_str(byte_idx(&self.temp_str, idx), &line);
+        self.prompt_cursor = Some(idx + line.chars().count());
+    }
+
+    /// Recalls an older entry of the history of the current mode.
+    pub fn history_prev(&mut self) {
+        let Some(hist) = self.prompt_history.get(&self.mode) else {
+            return;
+        };
+        let idx = match self.history_idx {
+            Some(0) => return,
+            Some(i) => i - 1,
+            None if hist.is_empty() => return,
+            None => {
+                self.history_draft = self.temp_str.clone();
+                hist.len() - 1
+            }
+        };
+        self.temp_str = hist[idx].clone();
+        self.history_idx = Some(idx);
+        self.prompt_cursor = None;
+    }
+
+    /// Recalls a newer entry of the history of the current mode, or what was
+    /// being typed before recalling.
+    pub fn history_next(&mut self) {
+        let Some(idx) = self.history_idx else {
+            return;
+        };
+        let len = self.prompt_history.get(&self.mode).map_or(0, |h| h.len());
+        if idx + 1 < len {
+            self.temp_str = self.prompt_history[&self.mode][idx + 1].clone();
+            self.history_idx = Some(idx + 1);
+        } else {
+            self.temp_str = std::mem::take(&mut self.history_draft);
+            self.history_idx = None;
+        }
+        self.prompt_cursor = None;
+    }
+
+    /// Adds `temp_str` to the history of the current mode as it is entered.
+    pub fn record_prompt(&mut self) {
+        if matches!(self.mode, Mode::Confirm) || self.temp_str.trim().is_empty() {
+            return;
+        }
+        let hist = self.prompt_history.entry(self.mode).or_default();
+        hist.retain(|h| *h != self.temp_str);
+        hist.push(self.temp_str.clone());
+        if hist.len() > HISTORY_LEN {
+            hist.remove(0);
+        }
+        if self.mode == Mode::Command {
+            save_history(hist);
+        }
+    }
+
+    /// The prompt with the cursor shown.
+    pub fn render_prompt(&self) -> String {
+        let idx = self.prompt_idx();
+        let chars: Vec<char> = self.temp_str.chars().collect();
+        let before: String = chars[..idx].iter().collect();
+        let at = chars.get(idx).copied().unwrap_or(' ');
+        let after: String = chars.get(idx + 1..).unwrap_or(&[]).iter().collect();
+        format!("{before}{}{at}\x1b[0m{after}", self.mode.cursor_style())
+    }
+}
@@ -0,0 +1,615 @@
+//! A small backtracking regex engine.
+//!
+//! Supports literals, `.`, classes (`[a-z]`, `[^...]`), the escapes `\d \w \s`
+//! (and their negations `\D \W \S`), anchors `^ $ \b \B`, groups `(...)`,
+//! non-capturing groups `(?:...)`, alternation `|` and the quantifiers
+//! `* + ? {n} {n,} {n,m}`, which are made lazy by a trailing `?`.
+//! Positions are char indices.
+//!
+//! Matching a line gives up with an error when it takes too many steps or
+//! recurses too deep, rather than freezing or missing matches.
+
+use std::cell::Cell;
+
+#[derive(Debug)]
+enum ClassItem {
+    Range(char, char),
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            ClassItem::Range(lo, hi) => *lo <= c && c <= *hi,
+            ClassItem::Digit(neg) => c.is_ascii_digit() != *neg,
+            ClassItem::Word(neg) => is_word(c) != *neg,
+            ClassItem::Space(neg) => c.is_whitespace() != *neg,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Node {
+    Char(char),
+    Any,
+    Class(Vec<ClassItem>, bool),
+    Start,
+    End,
+    WordBoundary(bool),
+    /// Alternatives, and the capture index if capturing.
+    Group(Vec<Vec<Node>>, Option<usize>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    },
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Capture groups of a match, as char ranges. Group 0 is the whole match.
+pub type Captures = Vec<Option<(usize, usize)>>;
+
+struct Parser<'a> {
+    chars: &'a [char],
+    pos: usize,
+    groups: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn alternatives(&mut self) -> Result<Vec<Vec<Node>>, String> {
+        let mut ret = vec![self.sequence()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            ret.push(self.sequence()?);
+        }
+        Ok(ret)
+    }
+
+    fn sequence(&mut self) -> Result<Vec<Node>, String> {
+        let mut ret = vec![];
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            ret.push(self.quantifier(atom)?);
+        }
+        Ok(ret)
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .ok()
+    }
+
+    fn quantifier(&mut self, atom: Node) -> Result<Node, String> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                let start = self.pos;
+                self.pos += 1;
+                let Some(min) = self.number() else {
+                    self.pos = start;
+                    return Ok(atom);
+                };
+                let max = if self.peek() == Some(',') {
+                    self.pos += 1;
+                    self.number()
+                } else {
+                    Some(min)
+                };
+                if self.peek() != Some('}') {
+                    self.pos = start;
+                    return Ok(atom);
+                }
+                if max.is_some_and(|m| m < min) {
+                    return Err("Invalid repetition range".to_string());
+                }
+                (min, max)
+            }
+            _ => return Ok(atom),
+        };
+        self.pos += 1;
+        if matches!(atom, Node::Start | Node::End | Node::WordBoundary(_)) {
+            return Err("Nothing to repeat".to_string());
+        }
+        let greedy = self.peek() != Some('?');
+        if !greedy {
+            self.pos += 1;
+        }
+        Ok(Node::Repeat {
+            node: Box::new(atom),
+            min,
+            max,
+            greedy,
+        })
+    }
+
+    fn escape(&mut self) -> Result<Result<ClassItem, char>, String> {
+        Ok(match self.next() {
+            Some('d') => Ok(ClassItem::Digit(false)),
+            Some('D') => Ok(ClassItem::Digit(true)),
+            Some('w') => Ok(ClassItem::Word(false)),
+            Some('W') => Ok(ClassItem::Word(true)),
+            Some('s') => Ok(ClassItem::Space(false)),
+            Some('S') => Ok(ClassItem::Space(true)),
+            Some('n') => Err('\n'),
+            Some('t') => Err('\t'),
+            Some(c) => Err(c),
+            None => return Err("Trailing backslash".to_string()),
+        })
+    }
+
+    fn class(&mut self) -> Result<Node, String> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut items = vec![];
+        let mut first = true;
+        loop {
+            let lo = match self.next() {
+                None => return Err("Unterminated class".to_string()),
+                Some(']') if !first => break,
+                Some('\\') => match self.escape()? {
+                    Ok(item) => {
+                        items.push(item);
+                        first = false;
+                        continue;
+                    }
+                    Err(c) => c,
+                },
+                Some(c) => c,
+            };
+            first = false;
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|c| *c != ']') {
+                self.pos += 1;
+                let hi = match self.next() {
+                    Some('\\') => match self.escape()? {
+                        Err(c) => c,
+                        Ok(_) => return Err("Invalid class range".to_string()),
+                    },
+                    Some(c) => c,
+                    None => return Err("Unterminated class".to_string()),
+                };
+                if hi < lo {
+                    return Err("Invalid class range".to_string());
+                }
+                items.push(ClassItem::Range(lo, hi));
+            } else {
+                items.push(ClassItem::Range(lo, lo));
+            }
+        }
+        Ok(Node::Class(items, negated))
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        Ok(match self.next() {
+            Some('.') => Node::Any,
+            Some('^') => Node::Start,
+            Some('$') => Node::End,
+            Some('[') => self.class()?,
+            Some('(') => {
+                let capture = if self.chars[self.pos..].starts_with(&['?', ':']) {
+                    self.pos += 2;
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups)
+                };
+                let alts = self.alternatives()?;
+                if self.next() != Some(')') {
+                    return Err("Unclosed group".to_string());
+                }
+                Node::Group(alts, capture)
+            }
+            Some(')') => return Err("Unopened group".to_string()),
+            Some('*' | '+' | '?') => return Err("Nothing to repeat".to_string()),
+            Some('\\') => match self.peek() {
+                Some('b') => {
+                    self.pos += 1;
+                    Node::WordBoundary(true)
+                }
+                Some('B') => {
+                    self.pos += 1;
+                    Node::WordBoundary(false)
+                }
+                _ => match self.escape()? {
+                    Ok(item) => Node::Class(vec![item], false),
+                    Err(c) => Node::Char(c),
+                },
+            },
+            Some(c) => Node::Char(c),
+            None => return Err("Unexpected end of pattern".to_string()),
+        })
+    }
+}
+
+/// Deepest recursion while matching. Repeats of anything but a single char
+/// recurse for every repetition, so they fail there rather than overflow the stack.
+const DEPTH_LIMIT: usize = 1000;
+
+/// Most steps taken to match a line are `STEP_LIMIT`, and `STEPS_PER_CHAR`
+/// more for every char of it.
+const STEP_LIMIT: usize = 1_000_000;
+const STEPS_PER_CHAR: usize = 100;
+
+/// Error of a match that went over the limits.
+const TOO_COMPLEX: &str = "Pattern too complex";
+
+/// Text being matched, and the steps left.
+struct Input<'a> {
+    text: &'a [char],
+    steps: Cell<usize>,
+    /// Whether the limits were hit, which makes every match fail.
+    failed: Cell<bool>,
+}
+
+impl Input<'_> {
+    /// Takes a step at `depth`, or fails if the limits are hit.
+    fn step(&self, depth: usize) -> bool {
+        match self.steps.get().checked_sub(1) {
+            Some(steps) if depth < DEPTH_LIMIT => self.steps.set(steps),
+            _ => self.failed.set(true),
+        }
+        !self.failed.get()
+    }
+}
+
+/// Continuation of a match, given where it ended and how deep the recursion is.
+type Cont<'a> = dyn FnMut(usize, usize, &mut Captures) -> bool + 'a;
+
+/// A repetition being matched, and the nodes following it.
+struct Repeat<'a> {
+    node: &'a Node,
+    min: usize,
+    max: Option<usize>,
+    greedy: bool,
+    rest: &'a [Node],
+}
+
+pub struct Regex {
+    root: Node,
+    groups: usize,
+}
+
+impl Regex {
+    /// Compiles a pattern.
+    pub fn new(pattern: &str) -> Result<Regex, String> {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut parser = Parser {
+            chars: &chars,
+            pos: 0,
+            groups: 0,
+        };
+        let alts = parser.alternatives()?;
+        if parser.pos < chars.len() {
+            return Err("Unopened group".to_string());
+        }
+        Ok(Regex {
+            root: Node::Group(alts, Some(0)),
+            groups: parser.groups,
+        })
+    }
+
+    fn single(node: &Node, text: &[char], pos: usize) -> bool {
+        let Some(&c) = text.get(pos) else {
+            return false;
+        };
+        match node {
+            Node::Char(ch) => c == *ch,
+            Node::Any => c != '\n',
+            Node::Class(items, negated) => items.iter().any(|i| i.matches(c)) != *negated,
+            _ => false,
+        }
+    }
+
+    fn match_here(
+        &self,
+        nodes: &[Node],
+        input: &Input,
+        pos: usize,
+        depth: usize,
+        caps: &mut Captures,
+        k: &mut Cont,
+    ) -> bool {
+        if !input.step(depth) {
+            return false;
+        }
+        let text = input.text;
+        let Some((first, rest)) = nodes.split_first() else {
+            return k(pos, depth, caps);
+        };
+        match first {
+            Node::Char(_) | Node::Any | Node::Class(..) => {
+                Regex::single(first, text, pos)
+                    && self.match_here(rest, input, pos + 1, depth + 1, caps, k)
+            }
+            Node::Start => pos == 0 && self.match_here(rest, input, pos, depth + 1, caps, k),
+            Node::End => pos == text.len() && self.match_here(rest, input, pos, depth + 1, caps, k),
+            Node::WordBoundary(b) => {
+                let before = pos > 0 && is_word(text[pos - 1]);
+                let after = text.get(pos).is_some_and(|c| is_word(*c));
+                (before != after) == *b && self.match_here(rest, input, pos, depth + 1, caps, k)
+            }
+            Node::Group(alts, capture) => {
+                let capture = *capture;
+                alts.iter().any(|alt| {
+                    self.match_here(alt, input, pos, depth + 1, caps, &mut |end, depth, caps| {
+                        let old = capture.map(|i| caps[i]);
+                        if let Some(i) = capture {
+                            caps[i] = Some((pos, end));
+                        }
+                        if self.match_here(rest, input, end, depth + 1, caps, k) {
+                            return true;
+                        }
+                        if let (Some(i), Some(old)) = (capture, old) {
+                            caps[i] = old;
+                        }
+                        false
+                    })
+                })
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                let repeat = Repeat {
+                    node,
+                    min: *min,
+                    max: *max,
+                    greedy: *greedy,
+                    rest,
+                };
+                self.repeat(&repeat, input, pos, 0, depth + 1, caps, k)
+            }
+        }
+    }
+
+    /// Matches `count` or more repetitions of a node at `pos`, then the rest.
+    #[allow(clippy::too_many_arguments)]
+    fn repeat(
+        &self,
+        r: &Repeat,
+        input: &Input,
+        pos: usize,
+        count: usize,
+        depth: usize,
+        caps: &mut Captures,
+        k: &mut Cont,
+    ) -> bool {
+        let text = input.text;
+        // Simple nodes repeat in a loop, without recursing for every char.
+        if matches!(r.node, Node::Char(_) | Node::Any | Node::Class(..)) {
+            let limit = r.max.unwrap_or(usize::MAX);
+            let mut n = count;
+            while n < limit && Regex::single(r.node, text, pos + n - count) {
+                n += 1;
+            }
+            if n < r.min {
+                return false;
+            }
+            let lo = r.min.saturating_sub(count);
+            let mut ends = lo..=n - count;
+            let mut rest = |i| self.match_here(r.rest, input, pos + i, depth + 1, caps, k);
+            return if r.greedy {
+                ends.rev().any(&mut rest)
+            } else {
+                ends.any(&mut rest)
+            };
+        }
+        let more = |caps: &mut Captures, k: &mut Cont| {
+            r.max.is_none_or(|m| count < m)
+                && self.match_here(
+                    std::slice::from_ref(r.node),
+                    input,
+                    pos,
+                    depth + 1,
+                    caps,
+                    &mut |end, depth, caps| {
+                        // Empty repetitions only count towards the minimum,
+                        // which keeps them from repeating forever.
+                        (end != pos || count < r.min)
+                            && self.repeat(r, input, end, count + 1, depth + 1, caps, k)
+                    },
+                )
+        };
+        let stop = |caps: &mut Captures, k: &mut Cont| {
+            count >= r.min && self.match_here(r.rest, input, pos, depth + 1, caps, k)
+        };
+        // Greedy repeats try to match once more before stopping, lazy ones after.
+        if r.greedy && more(caps, k) {
+            return true;
+        }
+        stop(caps, k) || (!r.greedy && more(caps, k))
+    }
+
+    /// Finds the first match starting at or after `start`.
+    fn find_at(&self, input: &Input, start: usize) -> Result<Option<Captures>, String> {
+        for pos in start..=input.text.len() {
+            let mut caps = vec![None; self.groups + 1];
+            let mut found = None;
+            let matched = self.match_here(
+                std::slice::from_ref(&self.root),
+                input,
+                pos,
+                0,
+                &mut caps,
+                &mut |_, _, caps| {
+                    found = Some(caps.clone());
+                    true
+                },
+            );
+            if input.failed.get() {
+                return Err(TOO_COMPLEX.to_string());
+            }
+            if matched {
+                return Ok(found);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Finds all non-overlapping matches in `text`.
+    pub fn find_all(&self, text: &str) -> Result<Vec<Captures>, String> {
+        let chars: Vec<char> = text.chars().collect();
+        let input = Input {
+            text: &chars,
+            steps: Cell::new(STEP_LIMIT + STEPS_PER_CHAR * chars.len()),
+            failed: Cell::new(false),
+        };
+        let mut ret = vec![];
+        let mut start = 0;
+        while start <= chars.len() {
+            let Some(caps) = self.find_at(&input, start)? else {
+                break;
+            };
+            let Some((s, e)) = caps[0] else {
+                break;
+            };
+            start = if e == s { e + 1 } else { e };
+            ret.push(caps);
+        }
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The char ranges of every match of `pattern` in `text`.
+    fn matches(pattern: &str, text: &str) -> Vec<(usize, usize)> {
+        Regex::new(pattern)
+            .unwrap()
+            .find_all(text)
+            .unwrap()
+            .iter()
+            .filter_map(|caps| caps[0])
+            .collect()
+    }
+
+    #[test]
+    fn literals_and_any() {
+        assert_eq!(matches("ab", "xabyab"), [(1, 3), (4, 6)]);
+        assert_eq!(matches("a.c", "abc a\nc"), [(0, 3)]);
+        assert_eq!(matches(r"a\.c", "abc a.c"), [(4, 7)]);
+    }
+
+    #[test]
+    fn anchors() {
+        assert_eq!(matches("^a", "aa"), [(0, 1)]);
+        assert_eq!(matches("a$", "aa"), [(1, 2)]);
+        assert_eq!(matches(r"\bfoo\b", "foo food foo"), [(0, 3), (9, 12)]);
+        assert_eq!(matches(r"\Boo", "foo oo"), [(1, 3)]);
+    }
+
+    #[test]
+    fn classes() {
+        assert_eq!(matches("[a-c]+", "xabcd"), [(1, 4)]);
+        assert_eq!(matches("[^a-c]", "abz"), [(2, 3)]);
+        assert_eq!(matches("[]a]", "]a"), [(0, 1), (1, 2)]);
+        assert_eq!(matches("[a-]", "-"), [(0, 1)]);
+        assert_eq!(matches(r"\d+", "a12b3"), [(1, 3), (4, 5)]);
+        assert_eq!(matches(r"\w+", "ab c_d"), [(0, 2), (3, 6)]);
+        assert_eq!(matches(r"\s", "a b"), [(1, 2)]);
+        assert_eq!(matches(r"[\d_]+", "a1_2b"), [(1, 4)]);
+        assert!(Regex::new("[z-a]").is_err());
+        assert!(Regex::new("[ab").is_err());
+    }
+
+    #[test]
+    fn alternation_and_groups() {
+        assert_eq!(matches("cat|dog", "dog cat"), [(0, 3), (4, 7)]);
+        assert_eq!(matches("a(b|c)d", "abd acd aed"), [(0, 3), (4, 7)]);
+        let caps = Regex::new(r"(\w+)@(?:x|(y))")
+            .unwrap()
+            .find_all("me@x")
+            .unwrap();
+        assert_eq!(caps[0], vec![Some((0, 4)), Some((0, 2)), None]);
+        assert!(Regex::new("(a").is_err());
+        assert!(Regex::new("a)").is_err());
+    }
+
+    #[test]
+    fn repeats() {
+        assert_eq!(matches("a{2,3}", "aaaa"), [(0, 3)]);
+        assert_eq!(matches("a{2}", "aaaaa"), [(0, 2), (2, 4)]);
+        assert_eq!(matches("a{2,}", "a aaaa"), [(2, 6)]);
+        assert_eq!(matches("a+?", "aaa"), [(0, 1), (1, 2), (2, 3)]);
+        assert_eq!(matches("<.*?>", "<a><b>"), [(0, 3), (3, 6)]);
+        assert_eq!(matches("(ab)+c", "ababc"), [(0, 5)]);
+        assert_eq!(matches("(a|b)*?c", "abc"), [(0, 3)]);
+        assert!(Regex::new("*a").is_err());
+        assert!(Regex::new("a{3,2}").is_err());
+    }
+
+    #[test]
+    fn nested_and_empty_repeats() {
+        assert_eq!(matches("^(a*)+$", ""), [(0, 0)]);
+        assert_eq!(matches("^(a*)+$", "aaa"), [(0, 3)]);
+        assert_eq!(matches("^(a?){3}$", "a"), [(0, 1)]);
+        assert_eq!(matches("^(a|b*)*$", "abba"), [(0, 4)]);
+        assert_eq!(matches("((ab)*c)+", "abcababcc"), [(0, 9)]);
+        assert_eq!(matches("x*", "ab"), [(0, 0), (1, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn long_repeats_fail_without_overflowing() {
+        // Search threads have the default stack size too.
+        std::thread::Builder::new()
+            .stack_size(2 * 1024 * 1024)
+            .spawn(|| {
+                for unit in ["ab", "abcdefghij"] {
+                    let text = unit.repeat(100_000);
+                    let re = Regex::new(&format!("({unit})*")).unwrap();
+                    assert_eq!(re.find_all(&text), Err(TOO_COMPLEX.to_string()));
+                }
+                assert_eq!(matches("(ab)*", &"ab".repeat(100)), [(0, 200), (200, 200)]);
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn too_many_steps_fail() {
+        let text = "a".repeat(40);
+        let re = Regex::new("(a*)*b").unwrap();
+        assert_eq!(re.find_all(&text), Err(TOO_COMPLEX.to_string()));
+        assert_eq!(matches("(a*)*b", "aaab"), [(0, 4)]);
+        // Long lines get more steps.
+        let text = "x".repeat(100_000) + "ab";
+        assert_eq!(
+            matches("a|b", &text),
+            [(100_000, 100_001), (100_001, 100_002)]
+        );
+    }
+}
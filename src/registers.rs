@@ -0,0 +1,220 @@
+//! Named registers and the kill ring.
+//!
+//! Everything copied or killed goes to `persistent_buffer`, which is what is
+//! pasted by default. Choosing a register `a` to `z` with `"` in nav mode makes
+//! the next copy go to it as well, or the next paste come from it. Copying to
+//! `A` to `Z` appends to the register instead, and `+` is the system clipboard.
+//!
+//! Killed text is also kept in a ring of recent kills, and a paste can be
+//! cycled through it. With `persist-registers = true` the named registers are
+//! kept in the `registers` file of the user configuration directory.
+
+use crate::buffer::{Alert, BimVar, Buffer};
+use crate::config;
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+};
+
+/// Most kills kept in the ring.
+const RING_LEN: usize = 32;
+
+/// Lines inserted by the last paste.
+pub struct LastPaste {
+    pub line: usize,
+    pub lines: Vec<String>,
+    /// Index in the ring of the pasted kill.
+    pub ring_idx: Option<usize>,
+}
+
+#[derive(Default)]
+pub struct Registers {
+    /// Named registers `a` to `z`.
+    pub named: HashMap<char, Vec<String>>,
+    /// Recent kills, latest first.
+    pub ring: VecDeque<Vec<String>>,
+    /// Register chosen for the next copy or paste.
+    pub selected: Option<char>,
+    pub last_paste: Option<LastPaste>,
+}
+
+impl Registers {
+    /// Loads the saved named registers.
+    pub fn load() -> Registers {
+        let mut ret = Registers::default();
+        let Some(contents) =
+            config::user_dir().and_then(|d| fs::read_to_string(d.join("registers")).ok())
+        else {
+            return ret;
+        };
+        let mut lines = contents.lines();
+        // Every register is a line of its name and line count, then its lines.
+        while let Some(header) = lines.next() {
+            let Some((name, count)) = header.split_once(' ') else {
+                break;
+            };
+            let (Some(name), Ok(count)) = (name.chars().next(), count.parse::<usize>()) else {
+                break;
+            };
+            let reg: Vec<String> = lines.by_ref().take(count).map(|l| l.to_string()).collect();
+            ret.named.insert(name, reg);
+        }
+        ret
+    }
+
+    fn save(&self) {
+        let Some(dir) = config::user_dir() else {
+            return;
+        };
+        let mut names: Vec<&char> = self.named.keys().collect();
+        names.sort();
+        let mut contents = String::new();
+        for name in names {
+            let reg = &self.named[name];
+            contents.push_str(&format!("{name} {}\n", reg.len()));
+            for l in reg {
+                contents.push_str(l);
+                contents.push('\n');
+            }
+        }
+        _ = fs::create_dir_all(&dir);
+        _ = fs::write(dir.join("registers"), contents);
+    }
+
+    /// Lines shown when listing the registers.
+    pub fn display(&self) -> Vec<String> {
+        let preview = |lines: &[String]| {
+            let first = lines.first().map_or("", |l| l.trim());
+            if lines.len() > 1 {
+                format!("{first} (+{} lines)", lines.len() - 1)
+            } else {
+                first.to_string()
+            }
+        };
+        let mut names: Vec<&char> = self.named.keys().collect();
+        names.sort();
+        let mut ret: Vec<String> = names
+            .into_iter()
+            .map(|n| format!("\"{n}  {}", preview(&self.named[n])))
+            .collect();
+        ret.extend(
+            self.ring
+                .iter()
+                .enumerate()
+                .map(|(i, k)| format!("k{i}  {}", preview(k))),
+        );
+        if ret.is_empty() {
+            ret.push("No registers".to_string());
+        }
+        ret
+    }
+}
+
+impl Buffer {
+    fn persist_registers(&self) -> bool {
+        matches!(self.vars.get("persist-registers"), Some(BimVar::Bool(true)))
+    }
+
+    /// Chooses the register of the next copy or paste.
+    pub fn select_register(&mut self, name: char) {
+        if name.is_ascii_alphabetic() || name == '+' {
+            self.registers.selected = Some(name);
+            self.alert = Alert::new(&[format!("Register \"{name}")], 1_000_000);
+        } else if name == '"' {
+            self.alert = Alert::new(&self.registers.display(), 5_000_000);
+        }
+    }
+
+    /// Copies lines, to the chosen register too if there is one.
+    pub fn yank(&mut self, lines: Vec<String>) {
+        let selected = self.registers.selected.take();
+        if selected == Some('+') || self.clipboard_sync() {
+            self.copy_to_clipboard(&lines);
+        }
+        if let Some(name) = selected.filter(|n| *n != '+') {
+            let reg = self
+                .registers
+                .named
+                .entry(name.to_ascii_lowercase())
+                .or_default();
+            if name.is_ascii_uppercase() {
+                reg.extend(lines.iter().cloned());
+            } else {
+                *reg = lines.clone();
+            }
+            if self.persist_registers() {
+                self.registers.save();
+            }
+        }
+        self.persistent_buffer = lines;
+    }
+
+    /// Copies killed lines and adds them to the kill ring.
+    pub fn kill(&mut self, lines: Vec<String>) {
+        if lines.iter().all(|l| l.is_empty()) && lines.len() < 2 {
+            return;
+        }
+        self.registers.ring.push_front(lines.clone());
+        self.registers.ring.truncate(RING_LEN);
+        self.yank(lines);
+    }
+
+    fn insert_paste(&mut self, lines: Vec<String>, ring_idx: Option<usize>) {
+        let line = self.cursor_pos.line;
+        for (i, l) in lines.iter().enumerate() {
+            self.contents.insert(line + i, l.to_string());
+        }
+        let len = self.contents[line].chars().count();
+        self.cursor_pos.idx = self.cursor_pos.idx.min(len);
+        self.registers.last_paste = Some(LastPaste {
+            line,
+            lines,
+            ring_idx,
+        });
+        self.update_highlighting();
+    }
+
+    /// Pastes the chosen register, or the last copy, above the cursor.
+    pub fn paste(&mut self) {
+        let lines = match self.registers.selected.take() {
+            Some('+') => {
+                self.paste_clipboard();
+                return;
+            }
+            Some(name) => match self.registers.named.get(&name.to_ascii_lowercase()) {
+                Some(reg) => reg.clone(),
+                None => {
+                    self.alert = Alert::new(&[format!("Register \"{name} is empty")], 1_000_000);
+                    return;
+                }
+            },
+            None => self.persistent_buffer.clone(),
+        };
+        let ring_idx = self.registers.ring.iter().position(|k| *k == lines);
+        self.insert_paste(lines, ring_idx);
+    }
+
+    /// Replaces the last paste with the kill before it in the ring.
+    pub fn paste_cycle(&mut self) {
+        let ring_len = self.registers.ring.len();
+        let last = match self.registers.last_paste.take() {
+            Some(last)
+                if ring_len > 0
+                    && self.contents.get(last.line..last.line + last.lines.len())
+                        == Some(&last.lines[..]) =>
+            {
+                last
+            }
+            _ => {
+                self.alert = Alert::new(&["No paste to cycle".to_string()], 1_000_000);
+                return;
+            }
+        };
+        self.contents.drain(last.line..last.line + last.lines.len());
+        let idx = last.ring_idx.map_or(0, |i| (i + 1) % ring_len);
+        let lines = self.registers.ring[idx].clone();
+        self.cursor_pos.line = last.line;
+        self.insert_paste(lines, Some(idx));
+        self.alert = Alert::new(&[format!("Kill {} of {ring_len}", idx + 1)], 1_000_000);
+    }
+}
@@ -0,0 +1,78 @@
+//! Repeating the last change with `.` in nav mode.
+//!
+//! Keys typed from the start of a nav command until nav mode is idle again are
+//! kept, and if the buffer was edited in between they become the last change.
+//! That covers deletes and kills, replacements, visual operators and insert
+//! sessions from leaving nav mode until coming back. Repeating plays the keys
+//! back like a macro.
+
+use crate::buffer::{Alert, Buffer, Mode};
+use crate::macros;
+use crossterm::event::{KeyCode, KeyEvent};
+
+#[derive(Default)]
+pub struct Repeat {
+    /// Keys of the command being typed.
+    pending: Vec<KeyEvent>,
+    /// Edits when the command started.
+    start: usize,
+    /// Keys of the last change, without its count.
+    pub last: Vec<KeyEvent>,
+    /// Count typed before the last change.
+    pub count: String,
+}
+
+fn digit(key: &KeyEvent) -> Option<char> {
+    match key.code {
+        KeyCode::Char(c) if c.is_ascii_digit() && key.modifiers.is_empty() => Some(c),
+        _ => None,
+    }
+}
+
+impl Buffer {
+    /// Keeps a typed key, and the command it ends if it edited the buffer.
+    pub fn track_change(&mut self, key: KeyEvent, prev_mode: Mode) {
+        if self.repeat.pending.is_empty() {
+            if prev_mode != Mode::Nav {
+                return;
+            }
+            self.repeat.start = self.edits;
+        }
+        self.repeat.pending.push(key);
+        if self.mode != Mode::Nav || !self.temp_str.chars().all(|c| c.is_ascii_digit()) {
+            return;
+        }
+        // A count alone doesn't end the command.
+        if !self.temp_str.is_empty() && digit(&key).is_some() {
+            return;
+        }
+        let keys = std::mem::take(&mut self.repeat.pending);
+        if self.repeat.start != self.edits {
+            let counted = keys.iter().take_while(|k| digit(k).is_some()).count();
+            self.repeat.count = keys[..counted].iter().filter_map(digit).collect();
+            self.repeat.last = keys[counted..].to_vec();
+        }
+    }
+
+    /// Queues the last change to be played again. A count replaces the count
+    /// of the change, or repeats it if it had none.
+    pub fn repeat_change(&mut self, count: Option<usize>) {
+        if self.repeat.last.is_empty() {
+            self.alert = Alert::new(&["No change to repeat".to_string()], 1_000_000);
+            return;
+        }
+        let times = match count {
+            Some(n) if self.repeat.count.is_empty() => n,
+            _ => 1,
+        };
+        let count = match count {
+            Some(n) if !self.repeat.count.is_empty() => n.to_string(),
+            _ => self.repeat.count.clone(),
+        };
+        let mut keys = macros::parse(&count).unwrap_or_default();
+        keys.extend(self.repeat.last.iter().copied());
+        for _ in 0..times.min(crate::nav::VIM_ITER_LIMIT) {
+            self.macros.playback.extend(keys.iter().copied());
+        }
+    }
+}
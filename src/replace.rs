@@ -0,0 +1,311 @@
+//! Project wide search and replace.
+//!
+//! Replacing from `*search` lists every match in `*replace` with the line
+//! before and after, grouped by file. Hits can be excluded before the
+//! replacement is applied, and every changed file can be restored afterwards.
+
+use crate::buffer::{Alert, Buffer, Mode, write_lines};
+use crate::direx;
+use crate::regex::Captures;
+use crate::search::Pattern;
+use std::{collections::HashMap, fs};
+
+/// Expands `$0`..`$9` and `${n}` in a replacement with the captures of a match.
+/// `$$` is a literal `$`.
+pub fn expand(template: &str, caps: &Captures, line: &[char]) -> String {
+    let group = |i: usize| {
+        caps.get(i)
+            .copied()
+            .flatten()
+            .map_or(String::new(), |(s, e)| line[s..e].iter().collect())
+    };
+    let t: Vec<char> = template.chars().collect();
+    let mut ret = String::new();
+    let mut i = 0;
+    while i < t.len() {
+        if t[i] != '$' {
+            ret.push(t[i]);
+            i += 1;
+            continue;
+        }
+        match t.get(i + 1) {
+            Some('$') => {
+                ret.push('$');
+                i += 2;
+            }
+            Some(d) if d.is_ascii_digit() => {
+                ret.push_str(&group(*d as usize - '0' as usize));
+                i += 2;
+            }
+            Some('{') => match t[i + 2..].iter().position(|c| *c == '}') {
+                Some(len) => {
+                    let n: String = t[i + 2..i + 2 + len].iter().collect();
+                    match n.parse::<usize>() {
+                        Ok(n) => ret.push_str(&group(n)),
+                        Err(_) => ret.extend(&t[i..i + 3 + len]),
+                    }
+                    i += 3 + len;
+                }
+                None => {
+                    ret.push('$');
+                    i += 1;
+                }
+            },
+            _ => {
+                ret.push('$');
+                i += 1;
+            }
+        }
+    }
+    ret
+}
+
+/// A single match to be replaced.
+pub struct ReplaceHit {
+    pub path: String,
+    /// Line number (subtracted by 1).
+    pub line: usize,
+    /// Line as it was when searched.
+    pub text: String,
+    /// Char range of the match.
+    pub range: (usize, usize),
+    pub replacement: String,
+    pub included: bool,
+}
+
+impl ReplaceHit {
+    /// The line with only this hit replaced.
+    fn replaced(&self) -> String {
+        let chars: Vec<char> = self.text.chars().collect();
+        let mut ret: String = chars[..self.range.0].iter().collect();
+        ret.push_str(&self.replacement);
+        ret.extend(&chars[self.range.1..]);
+        ret
+    }
+}
+
+/// Contents of a file before and after it was written.
+pub type Written = (Vec<String>, Vec<String>);
+
+/// State of the replacement shown in `*replace`.
+#[derive(Default)]
+pub struct Replace {
+    pub hits: Vec<ReplaceHit>,
+    /// Hit shown on each line of `*replace`.
+    pub line_hits: Vec<Option<usize>>,
+    /// Contents of files before and after replacements, latest last, by normalised path.
+    pub undo: HashMap<String, Vec<Written>>,
+}
+
+impl Replace {
+    /// Keeps the contents of a file before and after it was written, to undo it.
+    pub fn record(&mut self, path: &str, before: Vec<String>, after: &[String]) {
+        let key = direx::normalise(path).display().to_string();
+        // Files are written without trailing whitespace.
+        let after = after.iter().map(|l| l.trim_end().to_string()).collect();
+        self.undo.entry(key).or_default().push((before, after));
+    }
+
+    /// Renders the hits as lines, updating `line_hits`.
+    pub fn lines(&mut self) -> Vec<String> {
+        let mut ret = vec![];
+        self.line_hits.clear();
+        let mut path = None;
+        for (i, hit) in self.hits.iter().enumerate() {
+            if path != Some(&hit.path) {
+                path = Some(&hit.path);
+                ret.push(hit.path.clone());
+                self.line_hits.push(None);
+            }
+            let loc = format!("{}:{}", hit.line + 1, hit.range.0 + 1);
+            let mark = if hit.included { "[x]" } else { "[ ]" };
+            ret.push(format!("  {mark} {loc} - {}", hit.text));
+            ret.push(format!(
+                "      {} + {}",
+                " ".repeat(loc.len()),
+                hit.replaced()
+            ));
+            self.line_hits.push(Some(i));
+            self.line_hits.push(Some(i));
+        }
+        if ret.is_empty() {
+            ret.push(String::new());
+            self.line_hits.push(None);
+        }
+        ret
+    }
+
+    /// Summary of the included hits, for confirmation.
+    pub fn summary(&self) -> String {
+        let included: Vec<&ReplaceHit> = self.hits.iter().filter(|h| h.included).collect();
+        let mut files: Vec<&str> = included.iter().map(|h| h.path.as_str()).collect();
+        files.dedup();
+        format!(
+            "Replace {} of {} hits in {} files? (y/n)",
+            included.len(),
+            self.hits.len(),
+            files.len()
+        )
+    }
+}
+
+impl Buffer {
+    /// Handles the Ctrl keys of `*search` and `*replace`: `a` to replace the
+    /// search results, `w` to write edited results or apply the replacement and
+    /// `x` to exclude or include a hit.
+    pub fn replace_ctrl(&mut self, c: char) {
+        match (self.filepath.as_str(), c) {
+            ("*search", 'a') => {
+                if self.search.hits.is_empty() {
+                    return;
+                }
+                self.mode = Mode::ReplaceAll;
+                self.temp_str.clear();
+            }
+            ("*search", 'w') => self.search_write(),
+            ("*replace", 'x') => {
+                let Some(Some(i)) = self.replace.line_hits.get(self.cursor_pos.line).copied()
+                else {
+                    return;
+                };
+                self.replace.hits[i].included = !self.replace.hits[i].included;
+                self.contents = self.replace.lines();
+                self.update_highlighting();
+            }
+            ("*replace", 'w') if self.replace.hits.iter().any(|h| h.included) => {
+                self.mode = Mode::Confirm;
+            }
+            _ => {}
+        }
+    }
+
+    /// Lists what replacing the search results with `replacement` would do in `*replace`.
+    pub fn replace_preview(&mut self, replacement: &str) {
+        let pattern = match Pattern::parse(&self.search.query) {
+            Ok(p) => p,
+            Err(e) => {
+                self.alert = Alert::new(&[e], 1_000_000);
+                return;
+            }
+        };
+        let mut hits = vec![];
+        for hit in &self.search.hits {
+            let chars: Vec<char> = hit.text.chars().collect();
+            for caps in &hit.matches {
+                let Some(range) = caps[0] else {
+                    continue;
+                };
+                let replacement = match pattern {
+                    Pattern::Literal(_) => replacement.to_string(),
+                    Pattern::Regex(_) => expand(replacement, caps, &chars),
+                };
+                hits.push(ReplaceHit {
+                    path: hit.path.clone(),
+                    line: hit.line,
+                    text: hit.text.clone(),
+                    range,
+                    replacement,
+                    included: true,
+                });
+            }
+        }
+        self.replace.hits = hits;
+        self.open_special("*replace");
+        self.alert = Alert::new(
+            &["Ctrl-x to exclude a hit, Ctrl-w to apply".to_string()],
+            2_000_000,
+        );
+    }
+
+    /// Writes the included replacements, or does nothing if not `confirmed`.
+    /// Files that changed since the search are skipped.
+    pub fn replace_apply(&mut self, confirmed: bool) {
+        if !confirmed {
+            self.alert = Alert::new(&["Cancelled".to_string()], 1_000_000);
+            return;
+        }
+        let all_hits = std::mem::take(&mut self.replace.hits);
+        let mut by_file: Vec<(String, Vec<&ReplaceHit>)> = vec![];
+        for hit in all_hits.iter().filter(|h| h.included) {
+            match by_file.last_mut() {
+                Some((path, hits)) if *path == hit.path => hits.push(hit),
+                _ => by_file.push((hit.path.clone(), vec![hit])),
+            }
+        }
+        let mut errors = vec![];
+        let mut replaced = 0;
+        let mut files = 0;
+        for (path, mut hits) in by_file {
+            let Ok(contents) = fs::read_to_string(&path) else {
+                errors.push(format!("{path}: could not be read"));
+                continue;
+            };
+            let old: Vec<String> = contents.lines().map(|l| l.to_string()).collect();
+            if hits.iter().any(|h| old.get(h.line) != Some(&h.text)) {
+                errors.push(format!("{path}: changed since the search, skipped"));
+                continue;
+            }
+            let mut new = old.clone();
+            // Replacing from the end keeps the earlier ranges valid.
+            hits.sort_by_key(|h| std::cmp::Reverse((h.line, h.range.0)));
+            for hit in &hits {
+                let mut chars: Vec<char> = new[hit.line].chars().collect();
+                chars.splice(hit.range.0..hit.range.1, hit.replacement.chars());
+                new[hit.line] = chars.into_iter().collect();
+            }
+            match write_lines(&path, &new) {
+                Ok(()) => {
+                    self.replace.record(&path, old, &new);
+                    replaced += hits.len();
+                    files += 1;
+                }
+                Err(e) => errors.push(format!("{path}: {e}")),
+            }
+        }
+        self.contents = self.replace.lines();
+        self.cursor_pos.line = 0;
+        self.cursor_pos.idx = 0;
+        self.update_highlighting();
+        let mut msg = vec![format!("Replaced {replaced} hits in {files} files")];
+        let timeout = if errors.is_empty() {
+            1_000_000
+        } else {
+            5_000_000
+        };
+        msg.extend(errors);
+        self.alert = Alert::new(&msg, timeout);
+    }
+
+    /// Restores the current file to before the last project replace in it,
+    /// unless it was edited since.
+    pub fn undo_replace(&mut self) {
+        let key = direx::normalise(&self.filepath).display().to_string();
+        let Some((old, new)) = self.replace.undo.get(&key).and_then(|u| u.last()) else {
+            self.alert = Alert::new(&["No replace to undo".to_string()], 1_000_000);
+            return;
+        };
+        let current = self.contents.iter().map(|l| l.trim_end());
+        if !current.eq(new.iter().map(|l| l.as_str())) {
+            self.alert = Alert::new(
+                &["Changed since the replace, not undoing it".to_string()],
+                2_000_000,
+            );
+            return;
+        }
+        self.contents = old.clone();
+        if let Some(undo) = self.replace.undo.get_mut(&key) {
+            undo.pop();
+        }
+        if self.contents.is_empty() {
+            self.contents.push(String::new());
+        }
+        if self.cursor_pos.line >= self.contents.len() {
+            self.cursor_pos.line = self.contents.len() - 1;
+        }
+        let len = self.contents[self.cursor_pos.line].chars().count();
+        self.cursor_pos.idx = self.cursor_pos.idx.min(len);
+        self.update_highlighting();
+        self.save();
+        self.alert = Alert::new(&["Undid project replace".to_string()], 1_000_000);
+    }
+}
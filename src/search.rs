@@ -0,0 +1,299 @@
+//! Project wide search.
+//!
+//! Every project file is searched for a literal, or a regex if the query starts
+//! with `/`. Matching lines are listed in `*search` as `path:line:col: text`.
+//! Editing the text of the results and writing them changes the files.
+
+use crate::buffer::{Alert, Buffer, Cursor};
+use crate::direx;
+use crate::regex::{Captures, Regex};
+use std::{
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    thread,
+};
+
+/// What to search for.
+pub enum Pattern {
+    Literal(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    /// Parses a query. Queries starting with `/` are regexes.
+    pub fn parse(query: &str) -> Result<Pattern, String> {
+        match query.strip_prefix('/') {
+            Some(re) => Regex::new(re).map(Pattern::Regex),
+            None if query.is_empty() => Err("Empty query".to_string()),
+            None => Ok(Pattern::Literal(query.to_string())),
+        }
+    }
+
+    /// Finds the non-empty matches in a line, as char ranges with their captures.
+    /// Fails if the regex takes too long on the line.
+    pub fn find_all(&self, line: &str) -> Result<Vec<Captures>, String> {
+        Ok(match self {
+            Pattern::Literal(lit) => line
+                .match_indices(lit.as_str())
+                .map(|(i, m)| {
+                    let start = line[..i].chars().count();
+                    vec![Some((start, start + m.chars().count()))]
+                })
+                .collect(),
+            Pattern::Regex(re) => re
+                .find_all(line)?
+                .into_iter()
+                .filter(|c| c[0].is_some_and(|(s, e)| s != e))
+                .collect(),
+        })
+    }
+}
+
+/// A matching line.
+pub struct Hit {
+    pub path: String,
+    /// Line number (subtracted by 1).
+    pub line: usize,
+    /// Char index of the first match.
+    pub col: usize,
+    pub text: String,
+    /// Matches in the line.
+    pub matches: Vec<Captures>,
+    /// Hash of the contents of the file when it was searched.
+    pub hash: u64,
+}
+
+impl Hit {
+    /// Location part of the result line.
+    pub fn prefix(&self) -> String {
+        format!("{}:{}:{}: ", self.path, self.line + 1, self.col + 1)
+    }
+
+    pub fn display(&self) -> String {
+        format!("{}{}", self.prefix(), self.text)
+    }
+}
+
+/// Results of the last search.
+#[derive(Default)]
+pub struct Search {
+    pub query: String,
+    pub hits: Vec<Hit>,
+}
+
+impl Search {
+    pub fn lines(&self) -> Vec<String> {
+        let mut ret: Vec<String> = self.hits.iter().map(|h| h.display()).collect();
+        if ret.is_empty() {
+            ret.push(String::new());
+        }
+        ret
+    }
+}
+
+fn hash(contents: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Replaces lines of a file by number, keeping the other lines and the line
+/// endings as they are.
+fn replace_lines(contents: &str, edits: &[(usize, String)]) -> String {
+    let mut ret = String::with_capacity(contents.len());
+    for (i, line) in contents.split_inclusive('\n').enumerate() {
+        match edits.iter().find(|(l, _)| *l == i) {
+            Some((_, text)) => {
+                let body = line.trim_end_matches('\n').trim_end_matches('\r');
+                ret.push_str(text);
+                ret.push_str(&line[body.len()..]);
+            }
+            None => ret.push_str(line),
+        }
+    }
+    ret
+}
+
+fn search_file(pattern: &Pattern, path: &str) -> Result<Vec<Hit>, String> {
+    let Ok(contents) = fs::read_to_string(path) else {
+        return Ok(vec![]);
+    };
+    if contents.contains('\0') {
+        return Ok(vec![]);
+    }
+    let hash = hash(&contents);
+    let mut ret = vec![];
+    for (line, text) in contents.lines().enumerate() {
+        let matches = pattern
+            .find_all(text)
+            .map_err(|e| format!("{path}:{}: {e}", line + 1))?;
+        let Some((col, _)) = matches.first().and_then(|m| m[0]) else {
+            continue;
+        };
+        ret.push(Hit {
+            path: path.to_string(),
+            line,
+            col,
+            text: text.to_string(),
+            matches,
+            hash,
+        });
+    }
+    Ok(ret)
+}
+
+/// Searches files in parallel. Hits are in the order of `files`.
+/// Fails on the first line the regex takes too long on.
+pub fn search(pattern: &Pattern, files: &[String]) -> Result<Vec<Hit>, String> {
+    let threads = thread::available_parallelism().map_or(4, |n| n.get());
+    let chunk = files.len().div_ceil(threads).max(1);
+    thread::scope(|s| {
+        let handles: Vec<_> = files
+            .chunks(chunk)
+            .map(|c| {
+                s.spawn(move || {
+                    c.iter()
+                        .map(|f| search_file(pattern, f))
+                        .collect::<Result<Vec<_>, _>>()
+                })
+            })
+            .collect();
+        let mut ret = vec![];
+        for h in handles {
+            ret.extend(
+                h.join()
+                    .unwrap_or_else(|_| Ok(vec![]))?
+                    .into_iter()
+                    .flatten(),
+            );
+        }
+        Ok(ret)
+    })
+}
+
+impl Buffer {
+    /// Searches the project and shows the results in `*search`.
+    pub fn grep(&mut self, query: &str) {
+        let pattern = match Pattern::parse(query) {
+            Ok(p) => p,
+            Err(e) => {
+                self.alert = Alert::new(&[e], 1_000_000);
+                return;
+            }
+        };
+        let hits = match search(&pattern, &direx::get_dirs()) {
+            Ok(hits) => hits,
+            Err(e) => {
+                self.alert = Alert::new(&[e], 3_000_000);
+                return;
+            }
+        };
+        let mut files: Vec<&str> = hits.iter().map(|h| h.path.as_str()).collect();
+        files.dedup();
+        self.alert = Alert::new(
+            &[format!(
+                "{} matching lines in {} files",
+                hits.len(),
+                files.len()
+            )],
+            1_000_000,
+        );
+        self.search = Search {
+            query: query.to_string(),
+            hits,
+        };
+        self.open_special("*search");
+        self.cursor_pos = Cursor { line: 0, idx: 0 };
+    }
+
+    /// Opens the file of the search result under the cursor at its location.
+    pub fn search_select(&mut self) {
+        let Some(hit) = self.search.hits.get(self.cursor_pos.line) else {
+            return;
+        };
+        let (path, line, col) = (hit.path.clone(), hit.line, hit.col);
+        self.open_file(&path);
+        self.cursor_pos.line = line.min(self.contents.len() - 1);
+        self.cursor_pos.idx = col.min(self.contents[self.cursor_pos.line].chars().count());
+    }
+
+    /// Writes the edited text of the results back to their files, leaving
+    /// their other lines as they are. Files changed since the search are
+    /// skipped, keeping their edits in `*search`.
+    pub fn search_write(&mut self) {
+        if self.contents.len() != self.search.hits.len() {
+            self.alert = Alert::new(
+                &["Results can't be written after adding or removing lines".to_string()],
+                3_000_000,
+            );
+            return;
+        }
+        let mut errors = vec![];
+        // Edited lines by file, as (hit index, new text).
+        let mut edits: Vec<(String, Vec<(usize, String)>)> = vec![];
+        for (i, (hit, line)) in self.search.hits.iter().zip(&self.contents).enumerate() {
+            let Some(text) = line.strip_prefix(&hit.prefix()) else {
+                errors.push(format!(
+                    "{}:{}: location was edited, skipped",
+                    hit.path,
+                    hit.line + 1
+                ));
+                continue;
+            };
+            if text == hit.text {
+                continue;
+            }
+            match edits.iter_mut().find(|(p, _)| *p == hit.path) {
+                Some((_, e)) => e.push((i, text.to_string())),
+                None => edits.push((hit.path.clone(), vec![(i, text.to_string())])),
+            }
+        }
+        let pattern = Pattern::parse(&self.search.query).ok();
+        let mut written = 0;
+        let mut files = 0;
+        for (path, file_edits) in edits {
+            let Ok(contents) = fs::read_to_string(&path) else {
+                errors.push(format!("{path}: could not be read"));
+                continue;
+            };
+            let hits = &self.search.hits;
+            let now = hash(&contents);
+            if file_edits.iter().any(|(i, _)| hits[*i].hash != now) {
+                errors.push(format!("{path}: changed since the search, skipped"));
+                continue;
+            }
+            let lines: Vec<(usize, String)> = file_edits
+                .iter()
+                .map(|(i, text)| (hits[*i].line, text.clone()))
+                .collect();
+            let new = replace_lines(&contents, &lines);
+            if let Err(e) = fs::write(&path, &new) {
+                errors.push(format!("{path}: {e}"));
+                continue;
+            }
+            let old = contents.lines().map(|l| l.to_string()).collect();
+            let new: Vec<String> = new.lines().map(|l| l.to_string()).collect();
+            self.replace.record(&path, old, &new);
+            written += file_edits.len();
+            files += 1;
+            for (i, text) in file_edits {
+                let hit = &mut self.search.hits[i];
+                hit.matches = pattern
+                    .as_ref()
+                    .and_then(|p| p.find_all(&text).ok())
+                    .unwrap_or_default();
+                hit.text = text;
+                self.contents[i] = hit.display();
+            }
+        }
+        self.update_highlighting();
+        let mut msg = vec![format!("Wrote {written} lines in {files} files")];
+        let timeout = if errors.is_empty() {
+            1_000_000
+        } else {
+            5_000_000
+        };
+        msg.extend(errors);
+        self.alert = Alert::new(&msg, timeout);
+    }
+}
@@ -11,21 +11,21 @@ impl Snippet for Clang {
     }
     fn query(&self, query: &str) -> Vec<String> {
         match query.trim() {
-            "include" | "i" => vec!["#include <>".to_string()],
-            "define" | "d" => vec!["#define ".to_string()],
+            "include" | "i" => vec!["#include <$1>".to_string()],
+            "define" | "d" => vec!["#define ${1:NAME} $0".to_string()],
             "std" => vec![
                 "#include <stdio.h>".to_string(),
                 "#include <stdlib.h>".to_string(),
             ],
             "struct" => vec![
-                "typedef struct {".to_string(),
-                "    type field;".to_string(),
-                "} name;".to_string(),
+                "typedef struct ${1:name} {".to_string(),
+                "    ${2:type} ${3:field};$0".to_string(),
+                "} $1;".to_string(),
             ],
             "enum" => vec![
                 "typedef enum {".to_string(),
-                "    member,".to_string(),
-                "} name;".to_string(),
+                "    ${2:member},$0".to_string(),
+                "} ${1:name};".to_string(),
             ],
             _ => vec![],
         }
@@ -33,4 +33,13 @@ impl Snippet for Clang {
     fn display_str(&self) -> &'static str {
         "PDP-11"
     }
+    fn list(&self) -> Vec<(String, String)> {
+        vec![
+            ("include".to_string(), "#include <> (i)".to_string()),
+            ("define".to_string(), "#define (d)".to_string()),
+            ("std".to_string(), "stdio.h and stdlib.h".to_string()),
+            ("struct".to_string(), "typedef struct".to_string()),
+            ("enum".to_string(), "typedef enum".to_string()),
+        ]
+    }
 }
@@ -0,0 +1,104 @@
+//! Snippets loaded from snippet files.
+//!
+//! A snippet file holds any number of snippets in the form of:
+//! ```text
+//! # Comments start with a pound sign.
+//! snippet fn|f "Function definition"
+//! fn ${1:name}($2) {
+//!     $0
+//! }
+//! endsnippet
+//! ```
+//! Triggers separated by `|` are aliases. The description is optional.
+
+use crate::snippets::Snippet;
+use std::{fs, path::Path};
+
+pub struct SnippetEntry {
+    pub triggers: Vec<String>,
+    pub description: String,
+    pub body: Vec<String>,
+}
+
+pub struct FileSnippets {
+    pub entries: Vec<SnippetEntry>,
+    /// Whether `${sh:cmd}` runs in these snippets.
+    pub shell: bool,
+}
+
+impl FileSnippets {
+    /// Loads snippets from a snippet file.
+    pub fn load(path: &Path) -> Option<FileSnippets> {
+        fs::read_to_string(path)
+            .ok()
+            .map(|contents| FileSnippets::parse(&contents))
+    }
+
+    /// Parses the contents of a snippet file.
+    pub fn parse(contents: &str) -> FileSnippets {
+        let mut entries = vec![];
+        let mut current: Option<SnippetEntry> = None;
+        for line in contents.lines() {
+            match current.as_mut() {
+                Some(entry) => {
+                    if line.trim_end() == "endsnippet" {
+                        entries.extend(current.take());
+                    } else {
+                        entry.body.push(line.trim_end().to_string());
+                    }
+                }
+                None => {
+                    let Some(header) = line.strip_prefix("snippet ") else {
+                        continue;
+                    };
+                    let (triggers, description) = match header.trim().split_once(' ') {
+                        Some((t, d)) => (t, d.trim().trim_matches('"')),
+                        None => (header.trim(), ""),
+                    };
+                    current = Some(SnippetEntry {
+                        triggers: triggers.split('|').map(|t| t.to_string()).collect(),
+                        description: description.to_string(),
+                        body: vec![],
+                    });
+                }
+            }
+        }
+        entries.extend(current);
+        FileSnippets {
+            entries,
+            shell: true,
+        }
+    }
+}
+
+impl Snippet for FileSnippets {
+    fn is_kind(&self, _filepath: &str) -> bool {
+        true
+    }
+    fn query(&self, query: &str) -> Vec<String> {
+        self.entries
+            .iter()
+            .find(|e| e.triggers.iter().any(|t| t == query.trim()))
+            .map_or(vec![], |e| e.body.clone())
+    }
+    fn display_str(&self) -> &'static str {
+        "user"
+    }
+    fn shell(&self, _query: &str) -> bool {
+        self.shell
+    }
+    fn list(&self) -> Vec<(String, String)> {
+        self.entries
+            .iter()
+            .filter_map(|e| {
+                let (first, aliases) = e.triggers.split_first()?;
+                let description = if aliases.is_empty() {
+                    e.description.clone()
+                } else {
+                    format!("{} ({})", e.description, aliases.join(", "))
+                };
+                Some((first.clone(), description.trim().to_string()))
+            })
+            .collect()
+    }
+}
@@ -16,18 +16,25 @@ impl Snippet for Html {
                     "<html lang=\"en\">".to_string(),
                     "  <head>".to_string(),
                     "    <meta charset=\"utf-8\">".to_string(),
-                    "    <title>title</title>".to_string(),
+                    "    <title>${1:title}</title>".to_string(),
                     "  </head>".to_string(),
                     "  <body>".to_string(),
-                    "    <!-- page content -->".to_string(),
+                    "    <!-- ${2:page content} -->$0".to_string(),
                     "  </body>".to_string(),
                     "</html>".to_string(),
                 ]
             }
-            q => vec![format!("<{}>", q), "    ".to_string(), format!("</{}>", q)],
+            q => vec![
+                format!("<{}>", q),
+                "    $0".to_string(),
+                format!("</{}>", q),
+            ],
         }
     }
     fn display_str(&self) -> &'static str {
         "html"
     }
+    fn list(&self) -> Vec<(String, String)> {
+        vec![("init".to_string(), "HTML boilerplate".to_string())]
+    }
 }
@@ -4,6 +4,59 @@
 
 use crate::snippets::Snippet;
 
+/// Snippet triggers and the symbols they insert.
+const SYMBOLS: [(&str, &str); 49] = [
+    ("degree", "°"),
+    ("alpha", "α"),
+    ("beta", "β"),
+    ("gamma", "γ"),
+    ("delta", "δ"),
+    ("epsilon", "ε"),
+    ("zeta", "ζ"),
+    ("eta", "η"),
+    ("theta", "θ"),
+    ("iota", "ι"),
+    ("kappa", "κ"),
+    ("lambda", "λ"),
+    ("mu", "μ"),
+    ("nu", "ν"),
+    ("xi", "ξ"),
+    ("omicron", "ο"),
+    ("pi", "π"),
+    ("rho", "ρ"),
+    ("sigma", "σ"),
+    ("tau", "τ"),
+    ("upsilon", "υ"),
+    ("phi", "φ"),
+    ("chi", "χ"),
+    ("psi", "ψ"),
+    ("omega", "ω"),
+    ("Alpha", "Α"),
+    ("Beta", "Β"),
+    ("Gamma", "Γ"),
+    ("Delta", "Δ"),
+    ("Epsilon", "Ε"),
+    ("Zeta", "Ζ"),
+    ("Eta", "Η"),
+    ("Theta", "Θ"),
+    ("Iota", "Ι"),
+    ("Kappa", "Κ"),
+    ("Lambda", "Λ"),
+    ("Mu", "Μ"),
+    ("Nu", "Ν"),
+    ("Xi", "Ξ"),
+    ("Omicron", "Ο"),
+    ("Pi", "Π"),
+    ("Rho", "Ρ"),
+    ("Sigma", "Σ"),
+    ("Tau", "Τ"),
+    ("Upsilon", "Υ"),
+    ("Phi", "Φ"),
+    ("Chi", "Χ"),
+    ("Psi", "Ψ"),
+    ("Omega", "Ω"),
+];
+
 pub struct Markdown {}
 pub const MARKDOWN: Markdown = Markdown {};
 
@@ -12,61 +65,19 @@ impl Snippet for Markdown {
         filepath.ends_with(".md")
     }
     fn query(&self, query: &str) -> Vec<String> {
-        Vec::from([(match query {
-            "degree" => "°",
-            "alpha" => "α",
-            "beta" => "β",
-            "gamma" => "γ",
-            "delta" => "δ",
-            "epsilon" => "ε",
-            "zeta" => "ζ",
-            "eta" => "η",
-            "theta" => "θ",
-            "iota" => "ι",
-            "kappa" => "κ",
-            "lambda" => "λ",
-            "mu" => "μ",
-            "nu" => "ν",
-            "xi" => "ξ",
-            "omicron" => "ο",
-            "pi" => "π",
-            "rho" => "ρ",
-            "sigma" => "σ",
-            "tau" => "τ",
-            "upsilon" => "υ",
-            "phi" => "φ",
-            "chi" => "χ",
-            "psi" => "ψ",
-            "omega" => "ω",
-            "Alpha" => "Α",
-            "Beta" => "Β",
-            "Gamma" => "Γ",
-            "Delta" => "Δ",
-            "Epsilon" => "Ε",
-            "Zeta" => "Ζ",
-            "Eta" => "Η",
-            "Theta" => "Θ",
-            "Iota" => "Ι",
-            "Kappa" => "Κ",
-            "Lambda" => "Λ",
-            "Mu" => "Μ",
-            "Nu" => "Ν",
-            "Xi" => "Ξ",
-            "Omicron" => "Ο",
-            "Pi" => "Π",
-            "Rho" => "Ρ",
-            "Sigma" => "Σ",
-            "Tau" => "Τ",
-            "Upsilon" => "Υ",
-            "Phi" => "Φ",
-            "Chi" => "Χ",
-            "Psi" => "Ψ",
-            "Omega" => "Ω",
-            _ => "",
-        })
-        .to_string()])
+        Vec::from([SYMBOLS
+            .iter()
+            .find(|(trigger, _)| *trigger == query)
+            .map_or("", |(_, symbol)| symbol)
+            .to_string()])
     }
     fn display_str(&self) -> &'static str {
         "Markdown"
     }
+    fn list(&self) -> Vec<(String, String)> {
+        SYMBOLS
+            .iter()
+            .map(|(trigger, symbol)| (trigger.to_string(), symbol.to_string()))
+            .collect()
+    }
 }
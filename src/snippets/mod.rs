@@ -1,5 +1,8 @@
 //! Module for adding snippets.
 
+use crate::config;
+use std::fs;
+
 pub trait Snippet {
     /// Gets the snippet corresponding to a query string.
     fn query(&self, query: &str) -> Vec<String>;
@@ -7,8 +10,20 @@ pub trait Snippet {
     fn is_kind(&self, filepath: &str) -> bool;
     /// Returns string to display.
     fn display_str(&self) -> &'static str;
+    /// Lists the available snippets as (trigger, description).
+    fn list(&self) -> Vec<(String, String)>;
+    /// Whether the snippet for a query may run shell commands.
+    fn shell(&self, _query: &str) -> bool {
+        true
+    }
 }
 
+pub mod tabstop;
+pub mod vars;
+
+pub mod file;
+use file::*;
+
 pub mod text;
 use text::*;
 
@@ -24,16 +39,88 @@ use html::*;
 pub mod markdown;
 use markdown::*;
 
+/// Snippets from several sources. The first source with a snippet for a query wins.
+pub struct Merged {
+    sources: Vec<Box<dyn Snippet>>,
+    display: &'static str,
+}
+
+impl Snippet for Merged {
+    fn is_kind(&self, _filepath: &str) -> bool {
+        true
+    }
+    fn query(&self, query: &str) -> Vec<String> {
+        self.sources
+            .iter()
+            .map(|s| s.query(query))
+            .find(|lines| lines.iter().any(|l| !l.is_empty()))
+            .unwrap_or_default()
+    }
+    fn shell(&self, query: &str) -> bool {
+        self.sources
+            .iter()
+            .find(|s| s.query(query).iter().any(|l| !l.is_empty()))
+            .is_none_or(|s| s.shell(query))
+    }
+    fn display_str(&self) -> &'static str {
+        self.display
+    }
+    fn list(&self) -> Vec<(String, String)> {
+        let mut ret: Vec<(String, String)> = vec![];
+        for (trigger, description) in self.sources.iter().flat_map(|s| s.list()) {
+            if !ret.iter().any(|(t, _)| *t == trigger) {
+                ret.push((trigger, description));
+            }
+        }
+        ret
+    }
+}
+
+/// Gets the template for a new file at a path, from `templates/template.<ext>`
+/// in a config directory, and whether it may run shell commands.
+pub fn get_template(path: &str) -> Option<(Vec<String>, bool)> {
+    let ext = config::extension(path)?;
+    let file = config::files("templates", &format!("template.{ext}"))
+        .into_iter()
+        .next()?;
+    let contents = fs::read_to_string(&file).ok()?;
+    let lines = contents.lines().map(|l| l.to_string()).collect();
+    Some((lines, !config::in_project(&file)))
+}
+
+/// Gets snippets for a path. Snippet files for the path's extension come first,
+/// then `global.snip` files, then the built in snippets.
 pub fn get_snippets(path: &str) -> Box<dyn Snippet> {
-    if TODO.is_kind(path) {
-        Box::new(TODO)
-    } else if CLANG.is_kind(path) {
-        Box::new(CLANG)
-    } else if HTML.is_kind(path) {
-        Box::new(HTML)
-    } else if MARKDOWN.is_kind(path) {
-        Box::new(MARKDOWN)
+    let mut sources: Vec<Box<dyn Snippet>> = vec![];
+    let mut names = vec![];
+    if let Some(ext) = config::extension(path) {
+        names.push(format!("{ext}.snip"));
+    }
+    names.push("global.snip".to_string());
+    for name in names {
+        for file in config::files("snippets", &name) {
+            if let Some(mut snippets) = FileSnippets::load(&file) {
+                snippets.shell = !config::in_project(&file);
+                sources.push(Box::new(snippets));
+            }
+        }
+    }
+    let mut display = if sources.is_empty() {
+        TEXT.display_str()
     } else {
-        Box::new(TEXT)
+        "user"
+    };
+    let builtins: [Box<dyn Snippet>; 4] = [
+        Box::new(TODO),
+        Box::new(CLANG),
+        Box::new(HTML),
+        Box::new(MARKDOWN),
+    ];
+    for b in builtins {
+        if b.is_kind(path) {
+            display = b.display_str();
+            sources.push(b);
+        }
     }
+    Box::new(Merged { sources, display })
 }
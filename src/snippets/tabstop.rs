@@ -0,0 +1,489 @@
+//! Tab stops for snippets.
+//!
+//! Snippet lines may contain numbered tab stops (`$1`), placeholders with
+//! default text (`${2:default}`) and a final cursor position (`$0`).
+//! A number used more than once is mirrored, so editing the first occurrence
+//! updates the others. Write `\$`, `\}` and `\\` for the literal characters.
+//! Placeholders nested in a default, like `${1:a ${2:b}}`, are replaced by
+//! their own default and get no tab stop. Variables (see `vars`) are expanded
+//! before tab stops.
+
+use crate::buffer::{BimVar, Buffer};
+use crate::snippets::vars;
+
+/// A tab stop inside the buffer.
+#[derive(Clone, Copy)]
+pub struct TabStop {
+    /// Tab stop number. `0` is the final cursor position.
+    pub num: usize,
+    /// Line number (subtracted by 1).
+    pub line: usize,
+    /// Char index in line where the placeholder starts.
+    pub idx: usize,
+    /// Length of the placeholder in chars.
+    pub len: usize,
+}
+
+enum Token {
+    Text(String),
+    Stop(usize, Option<String>),
+}
+
+fn tokenize(line: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut text = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.peek() {
+                Some(&n) if matches!(n, '$' | '}' | '\\') => {
+                    text.push(n);
+                    _ = chars.next();
+                }
+                _ => text.push(c),
+            },
+            '$' if chars.peek().is_some_and(|n| n.is_ascii_digit()) => {
+                let mut num = String::new();
+                while let Some(&d) = chars.peek() {
+                    if !d.is_ascii_digit() {
+                        break;
+                    }
+                    num.push(d);
+                    _ = chars.next();
+                }
+                tokens.push(Token::Text(std::mem::take(&mut text)));
+                tokens.push(Token::Stop(num.parse().unwrap_or(0), None));
+            }
+            '$' if chars.peek() == Some(&'{') => {
+                let rest: String = chars.clone().skip(1).collect();
+                let digits: String = rest.chars().take_while(|d| d.is_ascii_digit()).collect();
+                let after = rest.chars().nth(digits.chars().count());
+                if digits.is_empty() || !matches!(after, Some(':' | '}')) {
+                    text.push(c);
+                    continue;
+                }
+                // Skip `{` and the digits.
+                for _ in 0..=digits.chars().count() {
+                    _ = chars.next();
+                }
+                let mut default = None;
+                if chars.next() == Some(':') {
+                    // The default is read as written, up to its own `}`.
+                    let mut raw = String::new();
+                    let mut depth = 0;
+                    while let Some(p) = chars.next() {
+                        match p {
+                            '\\' if matches!(chars.peek(), Some('$' | '}' | '\\')) => {
+                                raw.push(p);
+                                raw.push(chars.next().unwrap_or('\\'));
+                            }
+                            '$' if chars.peek() == Some(&'{') => {
+                                depth += 1;
+                                raw.push(p);
+                                raw.push(chars.next().unwrap_or('{'));
+                            }
+                            '}' if depth == 0 => break,
+                            '}' => {
+                                depth -= 1;
+                                raw.push(p);
+                            }
+                            _ => raw.push(p),
+                        }
+                    }
+                    default = Some(flatten(tokenize(&raw)));
+                }
+                tokens.push(Token::Text(std::mem::take(&mut text)));
+                tokens.push(Token::Stop(digits.parse().unwrap_or(0), default));
+            }
+            _ => text.push(c),
+        }
+    }
+    tokens.push(Token::Text(text));
+    tokens
+}
+
+/// Text of tokens with tab stops replaced by their defaults.
+fn flatten(tokens: Vec<Token>) -> String {
+    tokens
+        .into_iter()
+        .filter_map(|tk| match tk {
+            Token::Text(t) => Some(t),
+            Token::Stop(_, default) => default,
+        })
+        .collect()
+}
+
+/// Expands tab stops in snippet lines.
+/// Returns the lines as they should be inserted and the tab stops in them,
+/// with `line` relative to the first returned line.
+pub fn parse(lines: &[String]) -> (Vec<String>, Vec<TabStop>) {
+    let tokenized: Vec<Vec<Token>> = lines.iter().map(|l| tokenize(l)).collect();
+    let mut defaults: Vec<(usize, String)> = vec![];
+    for tk in tokenized.iter().flatten() {
+        if let Token::Stop(num, Some(default)) = tk
+            && !defaults.iter().any(|(n, _)| n == num)
+        {
+            defaults.push((*num, default.clone()));
+        }
+    }
+    let mut ret_lines = vec![];
+    let mut stops = vec![];
+    for (line, tokens) in tokenized.iter().enumerate() {
+        let mut ret_line = String::new();
+        let mut idx = 0;
+        for tk in tokens {
+            let text = match tk {
+                Token::Text(t) => t.as_str(),
+                Token::Stop(num, _) => {
+                    let text = defaults
+                        .iter()
+                        .find(|(n, _)| n == num)
+                        .map_or("", |(_, d)| d.as_str());
+                    stops.push(TabStop {
+                        num: *num,
+                        line,
+                        idx,
+                        len: text.chars().count(),
+                    });
+                    text
+                }
+            };
+            ret_line.push_str(text);
+            idx += text.chars().count();
+        }
+        ret_lines.push(ret_line);
+    }
+    (ret_lines, stops)
+}
+
+/// State of an expanded snippet whose tab stops are being visited.
+pub struct SnippetSession {
+    pub stops: Vec<TabStop>,
+    /// Tab stop numbers in the order they are visited. `$0` is always last.
+    order: Vec<usize>,
+    /// Index into `order` of the current tab stop.
+    current: usize,
+    /// Whether the current placeholder is untouched since it was jumped to.
+    /// Typing into a fresh placeholder replaces it.
+    pub fresh: bool,
+    /// Length of the current tab stop's line when last synced.
+    line_len: usize,
+    /// Number of lines in the buffer when last synced.
+    line_count: usize,
+}
+
+impl SnippetSession {
+    /// Index into `stops` of the first occurrence of the current tab stop.
+    pub fn active(&self) -> Option<usize> {
+        let num = *self.order.get(self.current)?;
+        self.stops.iter().position(|s| s.num == num)
+    }
+
+    /// Shifts tab stops on `line` starting at or after `from` by `delta` chars.
+    fn shift(&mut self, line: usize, from: usize, delta: isize, skip: usize) {
+        for (i, s) in self.stops.iter_mut().enumerate() {
+            if i != skip && s.line == line && s.idx >= from {
+                s.idx = s.idx.saturating_add_signed(delta);
+            }
+        }
+    }
+}
+
+/// Converts a char index into a byte index of `s`.
+fn byte_idx(s: &str, idx: usize) -> usize {
+    s.char_indices().nth(idx).map_or(s.len(), |(b, _)| b)
+}
+
+impl Buffer {
+    /// Gets the context snippet variables are expanded in.
+    /// `shell` allows `${sh:cmd}` to run.
+    pub fn snippet_context(&self, shell: bool) -> vars::Context<'_> {
+        let var = |name: &str| match self.vars.get(name) {
+            Some(BimVar::Str(s)) => s.as_str(),
+            _ => "",
+        };
+        vars::Context {
+            filepath: &self.filepath,
+            line: self.cursor_pos.line,
+            selection: &self.persistent_buffer,
+            date_fmt: var("date-fmt"),
+            time_fmt: var("time-fmt"),
+            shell,
+        }
+    }
+
+    /// Inserts snippet lines below the cursor at the current indent level and
+    /// starts visiting their tab stops, if there are any.
+    /// Return value signifies whether there are tab stops to fill in.
+    pub fn insert_snippet(&mut self, lines: &[String], shell: bool) -> bool {
+        let (sniplines, mut stops) = parse(&vars::expand(lines, &self.snippet_context(shell)));
+        let indent = self.lang.indent_size() * self.indent_lvl;
+        let first_line = self.cursor_pos.line + 1;
+        for (i, l) in sniplines.iter().enumerate() {
+            let mut ins_line = " ".repeat(indent);
+            ins_line.push_str(l);
+            self.contents.insert(first_line + i, ins_line);
+        }
+        self.update_highlighting();
+        for s in stops.iter_mut() {
+            s.line += first_line;
+            s.idx += indent;
+        }
+        self.start_snippet_session(stops)
+    }
+
+    /// Starts visiting tab stops, moving to the first one.
+    /// Return value signifies whether there are tab stops to fill in.
+    pub fn start_snippet_session(&mut self, stops: Vec<TabStop>) -> bool {
+        let mut order: Vec<usize> = stops.iter().map(|s| s.num).filter(|n| *n != 0).collect();
+        order.sort_unstable();
+        order.dedup();
+        let has_fields = !order.is_empty();
+        if stops.iter().any(|s| s.num == 0) {
+            order.push(0);
+        }
+        if order.is_empty() {
+            self.snippet_session = None;
+            return false;
+        }
+        self.snippet_session = Some(SnippetSession {
+            stops,
+            order,
+            current: 0,
+            fresh: true,
+            line_len: 0,
+            line_count: 0,
+        });
+        self.goto_tab_stop(0);
+        has_fields
+    }
+
+    /// Moves to the next tab stop of the current snippet.
+    /// Return value signifies whether there was a tab stop to move to.
+    pub fn next_tab_stop(&mut self) -> bool {
+        match &self.snippet_session {
+            Some(session) => {
+                let next = session.current + 1;
+                self.goto_tab_stop(next)
+            }
+            None => false,
+        }
+    }
+
+    /// Moves to the previous tab stop of the current snippet.
+    /// Return value signifies whether there was a tab stop to move to.
+    pub fn prev_tab_stop(&mut self) -> bool {
+        match &self.snippet_session {
+            Some(session) if session.current > 0 => {
+                let prev = session.current - 1;
+                self.goto_tab_stop(prev)
+            }
+            _ => false,
+        }
+    }
+
+    fn goto_tab_stop(&mut self, current: usize) -> bool {
+        let Some(mut session) = self.snippet_session.take() else {
+            return false;
+        };
+        session.current = current;
+        let Some(active) = session.active() else {
+            return false;
+        };
+        let stop = session.stops[active];
+        if stop.line >= self.contents.len() {
+            return false;
+        }
+        self.cursor_pos.line = stop.line;
+        self.cursor_pos.idx = stop.idx.min(self.contents[stop.line].chars().count());
+        session.fresh = stop.len != 0;
+        session.line_len = self.contents[stop.line].chars().count();
+        session.line_count = self.contents.len();
+        if stop.num != 0 {
+            self.snippet_session = Some(session);
+        }
+        true
+    }
+
+    /// Removes an untouched placeholder under the cursor so that typing replaces it.
+    /// Return value signifies whether anything was removed.
+    pub fn clear_placeholder(&mut self) -> bool {
+        let Some(session) = &mut self.snippet_session else {
+            return false;
+        };
+        let Some(active) = session.active() else {
+            return false;
+        };
+        let stop = session.stops[active];
+        if !session.fresh || self.cursor_pos.line != stop.line || self.cursor_pos.idx != stop.idx {
+            return false;
+        }
+        session.fresh = false;
+        let line = &mut self.contents[stop.line];
+        let (start, end) = (
+            byte_idx(line, stop.idx),
+            byte_idx(line, stop.idx + stop.len),
+        );
+        line.replace_range(start..end, "");
+        session.shift(stop.line, stop.idx + stop.len, -(stop.len as isize), active);
+        session.stops[active].len = 0;
+        session.line_len = self.contents[stop.line].chars().count();
+        self.sync_mirrors(active);
+        self.update_highlighting();
+        true
+    }
+
+    /// Keeps the current tab stop in sync with edits made inside it, updating its mirrors.
+    /// Edits elsewhere end the snippet session. Should be called after every edit.
+    pub fn sync_snippet(&mut self) {
+        let Some(session) = &mut self.snippet_session else {
+            return;
+        };
+        let Some(active) = session.active() else {
+            self.snippet_session = None;
+            return;
+        };
+        let stop = session.stops[active];
+        if session.line_count != self.contents.len() || stop.line >= self.contents.len() {
+            self.snippet_session = None;
+            return;
+        }
+        let line_len = self.contents[stop.line].chars().count();
+        if line_len == session.line_len {
+            return;
+        }
+        let delta = line_len as isize - session.line_len as isize;
+        let new_len = stop.len as isize + delta;
+        if new_len < 0
+            || self.cursor_pos.line != stop.line
+            || self.cursor_pos.idx < stop.idx
+            || self.cursor_pos.idx > stop.idx + new_len as usize
+        {
+            self.snippet_session = None;
+            return;
+        }
+        session.fresh = false;
+        session.shift(stop.line, stop.idx + stop.len, delta, active);
+        session.stops[active].len = new_len as usize;
+        self.sync_mirrors(active);
+    }
+
+    /// Copies the text of the tab stop at `active` into its mirrors.
+    fn sync_mirrors(&mut self, active: usize) {
+        let Some(mut session) = self.snippet_session.take() else {
+            return;
+        };
+        let stop = session.stops[active];
+        let text: String = self.contents[stop.line]
+            .chars()
+            .skip(stop.idx)
+            .take(stop.len)
+            .collect();
+        let text_len = text.chars().count();
+        let mut changed = false;
+        for i in 0..session.stops.len() {
+            let mirror = session.stops[i];
+            if i == active || mirror.num != stop.num || mirror.line >= self.contents.len() {
+                continue;
+            }
+            let line = &mut self.contents[mirror.line];
+            let (start, end) = (
+                byte_idx(line, mirror.idx),
+                byte_idx(line, mirror.idx + mirror.len),
+            );
+            if line[start..end] == text {
+                continue;
+            }
+            line.replace_range(start..end, &text);
+            let diff = text_len as isize - mirror.len as isize;
+            session.shift(mirror.line, mirror.idx + mirror.len, diff, i);
+            session.stops[i].len = text_len;
+            if self.cursor_pos.line == mirror.line && self.cursor_pos.idx > mirror.idx {
+                self.cursor_pos.idx = self.cursor_pos.idx.saturating_add_signed(diff);
+            }
+            changed = true;
+        }
+        if let Some(active) = session.active() {
+            let line = session.stops[active].line;
+            session.line_len = self.contents[line].chars().count();
+        }
+        session.line_count = self.contents.len();
+        self.snippet_session = Some(session);
+        if changed {
+            self.update_highlighting();
+        }
+    }
+
+    /// Whether the char at `line`, `idx` is part of an untouched placeholder.
+    pub fn in_placeholder(&self, line: usize, idx: usize) -> bool {
+        match &self.snippet_session {
+            Some(session) if session.fresh => session.active().is_some_and(|a| {
+                let s = session.stops[a];
+                s.line == line && idx >= s.idx && idx < s.idx + s.len
+            }),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses one line into its text and tab stops as (num, idx, len).
+    fn parse_line(line: &str) -> (String, Vec<(usize, usize, usize)>) {
+        let (lines, stops) = parse(&[line.to_string()]);
+        let stops = stops.iter().map(|s| (s.num, s.idx, s.len)).collect();
+        (lines[0].clone(), stops)
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(
+            parse_line(r"\$1 \} \\ \a"),
+            (r"$1 } \ \a".to_string(), vec![])
+        );
+        assert_eq!(parse_line("${x} $"), ("${x} $".to_string(), vec![]));
+    }
+
+    #[test]
+    fn numbered_stops() {
+        assert_eq!(
+            parse_line("a$1b$0"),
+            ("ab".to_string(), vec![(1, 1, 0), (0, 2, 0)])
+        );
+        assert_eq!(parse_line("$12"), (String::new(), vec![(12, 0, 0)]));
+        assert_eq!(parse_line("${3}x"), ("x".to_string(), vec![(3, 0, 0)]));
+    }
+
+    #[test]
+    fn placeholders() {
+        assert_eq!(
+            parse_line("f(${1:arg}) ${2:x\\}y}"),
+            ("f(arg) x}y".to_string(), vec![(1, 2, 3), (2, 7, 3)])
+        );
+    }
+
+    #[test]
+    fn mirrors() {
+        let (lines, stops) = parse(&["<${1:div}>".to_string(), "</$1>".to_string()]);
+        assert_eq!(lines, ["<div>", "</div>"]);
+        let stops: Vec<_> = stops
+            .iter()
+            .map(|s| (s.num, s.line, s.idx, s.len))
+            .collect();
+        assert_eq!(stops, [(1, 0, 1, 3), (1, 1, 2, 3)]);
+    }
+
+    #[test]
+    fn nested_placeholders() {
+        assert_eq!(
+            parse_line("${1:a ${2:b}} c"),
+            ("a b c".to_string(), vec![(1, 0, 3)])
+        );
+        assert_eq!(
+            parse_line("${1:x ${2:${3:y}} $4}!"),
+            ("x y !".to_string(), vec![(1, 0, 4)])
+        );
+    }
+}
@@ -14,4 +14,7 @@ impl Snippet for Text {
     fn display_str(&self) -> &'static str {
         "Text"
     }
+    fn list(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
 }
@@ -11,12 +11,18 @@ impl Snippet for Todo {
     }
     fn query(&self, query: &str) -> Vec<String> {
         match query.trim() {
-            "newtask" | "n" => vec!["[ ] ".to_string()],
-            "asap" | "a" => vec!["[ ]!".to_string()],
+            "newtask" | "n" => vec!["[ ] $0".to_string()],
+            "asap" | "a" => vec!["[ ]!$0".to_string()],
             _ => vec![],
         }
     }
     fn display_str(&self) -> &'static str {
         "Ssorgn't"
     }
+    fn list(&self) -> Vec<(String, String)> {
+        vec![
+            ("newtask".to_string(), "[ ] (n)".to_string()),
+            ("asap".to_string(), "[ ]! (a)".to_string()),
+        ]
+    }
 }
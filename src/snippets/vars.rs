@@ -0,0 +1,303 @@
+//! Variables in snippets, expanded when the snippet is inserted.
+//!
+//! | Variable       | Value                                              |
+//! |----------------|----------------------------------------------------|
+//! | `$FILENAME`    | Name of the current file                           |
+//! | `$FILESTEM`    | Name of the current file without its extension     |
+//! | `$FILEPATH`    | Path of the current file                           |
+//! | `$DIRECTORY`   | Directory of the current file                      |
+//! | `$LINE`        | Current line number                                |
+//! | `$DATE`        | Current date, formatted with the `date-fmt` var    |
+//! | `$TIME`        | Current time, formatted with the `time-fmt` var    |
+//! | `$SELECTION`   | Contents of the persistent buffer                  |
+//! | `${env:NAME}`  | Environment variable `NAME`                        |
+//! | `${sh:cmd}`    | Output of the shell command `cmd`                  |
+//!
+//! `${sh:cmd}` expands to nothing in snippets and templates from the project
+//! directory, which shouldn't run commands just by being inserted.
+//!
+//! Variables can be written as `${NAME}` too. `${DATE:fmt}` and `${TIME:fmt}`
+//! override the format, which uses `strftime` style `%` sequences.
+
+use crate::shell_command;
+use std::{env, path::Path, sync::OnceLock, time};
+
+/// Information about the buffer that variables are expanded from.
+pub struct Context<'a> {
+    pub filepath: &'a str,
+    /// Line number (subtracted by 1).
+    pub line: usize,
+    pub selection: &'a [String],
+    pub date_fmt: &'a str,
+    pub time_fmt: &'a str,
+    /// Whether `${sh:cmd}` runs its command.
+    pub shell: bool,
+}
+
+impl Context<'_> {
+    fn value(&self, name: &str, arg: Option<&str>) -> Option<String> {
+        let path = Path::new(self.filepath);
+        let os_str = |s: Option<&std::ffi::OsStr>| {
+            s.map_or(String::new(), |s| s.to_string_lossy().to_string())
+        };
+        Some(match (name, arg) {
+            ("FILENAME", None) => os_str(path.file_name()),
+            ("FILESTEM", None) => os_str(path.file_stem()),
+            ("FILEPATH", None) => self.filepath.to_string(),
+            ("DIRECTORY", None) => path
+                .parent()
+                .map_or(String::new(), |p| p.display().to_string()),
+            ("LINE", None) => (self.line + 1).to_string(),
+            ("DATE", fmt) => DateTime::now().format(fmt.unwrap_or(self.date_fmt)),
+            ("TIME", fmt) => DateTime::now().format(fmt.unwrap_or(self.time_fmt)),
+            ("SELECTION", None) => self.selection.join("\n"),
+            ("env", Some(var)) => env::var(var).unwrap_or_default(),
+            ("sh", Some(_)) if !self.shell => String::new(),
+            ("sh", Some(cmd)) => match shell_command(cmd).output() {
+                Ok(o) => String::from_utf8_lossy(&o.stdout).trim_end().to_string(),
+                Err(_) => String::new(),
+            },
+            _ => return None,
+        })
+    }
+}
+
+/// Escapes a value so that tab stop parsing leaves it as is.
+fn escape(value: &str) -> String {
+    let mut ret = String::new();
+    for c in value.chars() {
+        if matches!(c, '$' | '}' | '\\') {
+            ret.push('\\');
+        }
+        ret.push(c);
+    }
+    ret
+}
+
+/// Parses a variable following a `$`. Returns its value and its length in chars.
+fn parse_var(text: &[char], ctx: &Context) -> Option<(String, usize)> {
+    if text.first() != Some(&'{') {
+        let name: String = text
+            .iter()
+            .take_while(|c| c.is_ascii_uppercase() || **c == '_')
+            .collect();
+        return ctx.value(&name, None).map(|v| (v, name.len()));
+    }
+    let name: String = text[1..]
+        .iter()
+        .take_while(|c| c.is_ascii_alphabetic() || **c == '_')
+        .collect();
+    let mut i = 1 + name.len();
+    let arg = match text.get(i)? {
+        '}' => {
+            i += 1;
+            None
+        }
+        ':' => {
+            i += 1;
+            let mut arg = String::new();
+            loop {
+                match text.get(i)? {
+                    '\\' => {
+                        arg.push(*text.get(i + 1)?);
+                        i += 2;
+                    }
+                    '}' => {
+                        i += 1;
+                        break;
+                    }
+                    c => {
+                        arg.push(*c);
+                        i += 1;
+                    }
+                }
+            }
+            Some(arg)
+        }
+        _ => return None,
+    };
+    ctx.value(&name, arg.as_deref()).map(|v| (v, i))
+}
+
+/// Expands variables in snippet lines.
+/// Values spanning several lines split the line they are in.
+pub fn expand(lines: &[String], ctx: &Context) -> Vec<String> {
+    let text: Vec<char> = lines.join("\n").chars().collect();
+    let mut ret = String::new();
+    let mut i = 0;
+    while let Some(&c) = text.get(i) {
+        match c {
+            '\\' => {
+                ret.push(c);
+                ret.extend(text.get(i + 1));
+                i += 2;
+            }
+            '$' => match parse_var(&text[i + 1..], ctx) {
+                Some((value, len)) => {
+                    ret.push_str(&escape(&value));
+                    i += len + 1;
+                }
+                None => {
+                    ret.push(c);
+                    i += 1;
+                }
+            },
+            _ => {
+                ret.push(c);
+                i += 1;
+            }
+        }
+    }
+    ret.split('\n').map(|l| l.to_string()).collect()
+}
+
+/// Local date and time.
+pub struct DateTime {
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    /// Days since Sunday.
+    weekday: u32,
+    /// Days since January 1st.
+    yearday: u32,
+    /// Offset from UTC in seconds.
+    offset: i64,
+}
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+const WEEKDAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+/// Gets the local offset from UTC in seconds.
+/// Asks `date` the first time, as the standard library has no notion of time zones.
+fn utc_offset() -> i64 {
+    static OFFSET: OnceLock<i64> = OnceLock::new();
+    *OFFSET.get_or_init(|| {
+        if cfg!(target_os = "windows") {
+            return 0;
+        }
+        let Ok(o) = shell_command("date +%z").output() else {
+            return 0;
+        };
+        let out = String::from_utf8_lossy(&o.stdout);
+        let out = out.trim();
+        let sign = if out.starts_with('-') { -1 } else { 1 };
+        let digits = out.trim_start_matches(['+', '-']);
+        match (digits.get(0..2), digits.get(2..4)) {
+            (Some(h), Some(m)) => {
+                sign * (h.parse::<i64>().unwrap_or(0) * 3600 + m.parse::<i64>().unwrap_or(0) * 60)
+            }
+            _ => 0,
+        }
+    })
+}
+
+impl DateTime {
+    pub fn now() -> DateTime {
+        let secs = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+        DateTime::from_unix(secs, utc_offset())
+    }
+
+    /// Converts a unix timestamp to a date in the time zone `offset` seconds from UTC.
+    pub fn from_unix(secs: i64, offset: i64) -> DateTime {
+        let local = secs + offset;
+        let days = local.div_euclid(86400);
+        let rem = local.rem_euclid(86400);
+        // Civil from days, see http://howardhinnant.github.io/date_algorithms.html
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + i64::from(month <= 2);
+        let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+        let cumulative = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+        let yearday = cumulative[month as usize - 1] + day - 1 + u32::from(leap && month > 2);
+        DateTime {
+            year,
+            month,
+            day,
+            hour: (rem / 3600) as u32,
+            minute: (rem % 3600 / 60) as u32,
+            second: (rem % 60) as u32,
+            weekday: (days + 4).rem_euclid(7) as u32,
+            yearday,
+            offset,
+        }
+    }
+
+    /// Formats the date with `strftime` style sequences:
+    /// `%Y %y %m %d %e %H %I %M %S %p %a %A %b %B %j %z %%`.
+    pub fn format(&self, fmt: &str) -> String {
+        let mut ret = String::new();
+        let mut chars = fmt.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                ret.push(c);
+                continue;
+            }
+            let hour12 = match self.hour % 12 {
+                0 => 12,
+                h => h,
+            };
+            match chars.next() {
+                Some('Y') => ret.push_str(&self.year.to_string()),
+                Some('y') => ret.push_str(&format!("{:02}", self.year.rem_euclid(100))),
+                Some('m') => ret.push_str(&format!("{:02}", self.month)),
+                Some('d') => ret.push_str(&format!("{:02}", self.day)),
+                Some('e') => ret.push_str(&format!("{:2}", self.day)),
+                Some('H') => ret.push_str(&format!("{:02}", self.hour)),
+                Some('I') => ret.push_str(&format!("{hour12:02}")),
+                Some('M') => ret.push_str(&format!("{:02}", self.minute)),
+                Some('S') => ret.push_str(&format!("{:02}", self.second)),
+                Some('p') => ret.push_str(if self.hour < 12 { "AM" } else { "PM" }),
+                Some('a') => ret.push_str(&WEEKDAYS[self.weekday as usize][..3]),
+                Some('A') => ret.push_str(WEEKDAYS[self.weekday as usize]),
+                Some('b') => ret.push_str(&MONTHS[self.month as usize - 1][..3]),
+                Some('B') => ret.push_str(MONTHS[self.month as usize - 1]),
+                Some('j') => ret.push_str(&format!("{:03}", self.yearday + 1)),
+                Some('z') => {
+                    let sign = if self.offset < 0 { '-' } else { '+' };
+                    let abs = self.offset.abs();
+                    ret.push_str(&format!("{sign}{:02}{:02}", abs / 3600, abs % 3600 / 60));
+                }
+                Some('%') => ret.push('%'),
+                Some(other) => {
+                    ret.push('%');
+                    ret.push(other);
+                }
+                None => ret.push('%'),
+            }
+        }
+        ret
+    }
+}
@@ -0,0 +1,355 @@
+//! Visual mode: selecting text with the nav motions and operating on it.
+//!
+//! The selection spans from `Buffer::anchor` to the cursor, both included.
+//! It is made of characters, whole lines or a block of columns.
+
+use crate::Mods;
+use crate::buffer::{Alert, BimVar, Buffer, Cursor, Mode};
+use crate::nav::handle_nav;
+use crossterm::event::{self, KeyCode};
+use std::{io::Write, process::Stdio, thread};
+
+/// Shape of the visual selection.
+#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Select {
+    Char,
+    Line,
+    Block,
+}
+
+/// Swaps the case of every char.
+pub fn toggle_case(s: &str) -> String {
+    s.chars()
+        .map(|c| {
+            if c.is_uppercase() {
+                c.to_lowercase().collect::<String>()
+            } else {
+                c.to_uppercase().collect()
+            }
+        })
+        .collect()
+}
+
+impl Buffer {
+    /// Starts selecting at the cursor, or changes the shape of the selection.
+    /// Selecting with the current shape again stops selecting.
+    pub fn visual(&mut self, kind: Select) {
+        match self.mode {
+            Mode::Visual(k) if k == kind => self.mode = Mode::Nav,
+            Mode::Visual(_) => self.mode = Mode::Visual(kind),
+            _ => {
+                self.anchor = self.cursor_pos;
+                self.mode = Mode::Visual(kind);
+            }
+        }
+        self.temp_str.clear();
+    }
+
+    /// Start and end of the selection, in order.
+    fn selection_bounds(&self) -> (Cursor, Cursor) {
+        let (a, c) = (self.anchor, self.cursor_pos);
+        if (a.line, a.idx) <= (c.line, c.idx) {
+            (a, c)
+        } else {
+            (c, a)
+        }
+    }
+
+    /// Char range of `line` in the selection, clamped to the line.
+    fn selection_range(&self, kind: Select, line: usize) -> (usize, usize) {
+        let (start, end) = self.selection_bounds();
+        let len = self.contents[line].chars().count();
+        let (a, b) = match kind {
+            Select::Char => (
+                if line == start.line { start.idx } else { 0 },
+                if line == end.line { end.idx + 1 } else { len },
+            ),
+            Select::Line => (0, len),
+            Select::Block => {
+                let lo = self.anchor.idx.min(self.cursor_pos.idx);
+                let hi = self.anchor.idx.max(self.cursor_pos.idx);
+                (lo, hi + 1)
+            }
+        };
+        (a.min(len), b.min(len))
+    }
+
+    /// Whether the char at `line`, `idx` is selected.
+    pub fn in_selection(&self, line: usize, idx: usize) -> bool {
+        let Mode::Visual(kind) = self.mode else {
+            return false;
+        };
+        let (start, end) = self.selection_bounds();
+        if line < start.line || line > end.line {
+            return false;
+        }
+        match kind {
+            Select::Char => {
+                (line, idx) >= (start.line, start.idx) && (line, idx) <= (end.line, end.idx)
+            }
+            Select::Line => true,
+            Select::Block => {
+                idx >= self.anchor.idx.min(self.cursor_pos.idx)
+                    && idx <= self.anchor.idx.max(self.cursor_pos.idx)
+            }
+        }
+    }
+
+    /// The selected text, as lines.
+    pub fn selected_text(&self, kind: Select) -> Vec<String> {
+        let (start, end) = self.selection_bounds();
+        (start.line..=end.line)
+            .map(|l| {
+                let (a, b) = self.selection_range(kind, l);
+                self.contents[l].chars().skip(a).take(b - a).collect()
+            })
+            .collect()
+    }
+
+    /// Replaces the selected chars of every line with `f` of them.
+    fn map_selection(&mut self, kind: Select, f: impl Fn(&str) -> String) {
+        let (start, end) = self.selection_bounds();
+        for l in start.line..=end.line {
+            let (a, b) = self.selection_range(kind, l);
+            let chars: Vec<char> = self.contents[l].chars().collect();
+            let selected: String = chars[a..b].iter().collect();
+            let mut new: String = chars[..a].iter().collect();
+            new.push_str(&f(&selected));
+            new.extend(&chars[b..]);
+            self.contents[l] = new;
+        }
+    }
+
+    /// Deletes the selection. A char selection past the end of a line takes
+    /// the line break with it.
+    fn delete_selection(&mut self, kind: Select) {
+        let (start, end) = self.selection_bounds();
+        match kind {
+            Select::Char => {
+                let (a, _) = self.selection_range(kind, start.line);
+                let (_, b) = self.selection_range(kind, end.line);
+                let end_len = self.contents[end.line].chars().count();
+                let mut last = end.line;
+                let mut rest: String = self.contents[end.line].chars().skip(b).collect();
+                if end.idx >= end_len && end.line + 1 < self.contents.len() {
+                    last += 1;
+                    rest = self.contents[last].clone();
+                }
+                let mut new: String = self.contents[start.line].chars().take(a).collect();
+                new.push_str(&rest);
+                self.contents.splice(start.line..=last, [new]);
+                self.cursor_pos = Cursor {
+                    line: start.line,
+                    idx: a,
+                };
+            }
+            Select::Line => {
+                self.contents.drain(start.line..=end.line);
+                if self.contents.is_empty() {
+                    self.contents.push(String::new());
+                }
+                self.cursor_pos = Cursor {
+                    line: start.line.min(self.contents.len() - 1),
+                    idx: 0,
+                };
+            }
+            Select::Block => {
+                self.map_selection(kind, |_| String::new());
+                let lo = self.anchor.idx.min(self.cursor_pos.idx);
+                let len = self.contents[start.line].chars().count();
+                self.cursor_pos = Cursor {
+                    line: start.line,
+                    idx: lo.min(len),
+                };
+            }
+        }
+    }
+
+    /// Indents the selected lines by one level, or dedents them.
+    fn indent_selection(&mut self, dedent: bool) {
+        let (start, end) = self.selection_bounds();
+        self.indent_lines(start.line, end.line, dedent);
+    }
+
+    /// Indents lines `first` to `last` by one level, or dedents them.
+    pub fn indent_lines(&mut self, first: usize, last: usize, dedent: bool) {
+        let size = self.lang.indent_size();
+        for line in &mut self.contents[first..=last] {
+            if dedent {
+                let spaces = line.chars().take(size).take_while(|c| *c == ' ').count();
+                line.replace_range(..spaces, "");
+            } else if !line.is_empty() {
+                line.insert_str(0, &" ".repeat(size));
+            }
+        }
+    }
+
+    /// Pipes the selected lines through a shell command, replacing them with its output.
+    pub fn filter_selection(&mut self, cmd: &str) {
+        let (start, end) = self.selection_bounds();
+        let input = self.contents[start.line..=end.line].join("\n") + "\n";
+        let child = crate::shell_command(cmd)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+        let mut child = match child {
+            Ok(c) => c,
+            Err(e) => {
+                self.alert = Alert::new(&[format!("Command failed: {e}")], 1_000_000);
+                return;
+            }
+        };
+        // Written from another thread so that a command writing a lot doesn't block on us.
+        if let Some(mut stdin) = child.stdin.take() {
+            thread::spawn(move || _ = stdin.write_all(input.as_bytes()));
+        }
+        let output = match child.wait_with_output() {
+            Ok(o) => o,
+            Err(e) => {
+                self.alert = Alert::new(&[format!("Command failed: {e}")], 1_000_000);
+                return;
+            }
+        };
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+            self.alert = Alert::new(&[stderr, format!("{}", output.status)], 5_000_000);
+            return;
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut lines: Vec<String> = stdout.lines().map(|l| l.to_string()).collect();
+        if lines.is_empty() {
+            lines.push(String::new());
+        }
+        self.contents.splice(start.line..=end.line, lines);
+        self.cursor_pos = Cursor {
+            line: start.line.min(self.contents.len() - 1),
+            idx: 0,
+        };
+        self.update_highlighting();
+    }
+
+    /// Applies the operator `c` to the selection. Returns false if `c` isn't an operator.
+    fn visual_operator(&mut self, kind: Select, c: char) -> bool {
+        match c {
+            'y' => {
+                self.yank(self.selected_text(kind));
+                self.cursor_pos = self.selection_bounds().0;
+            }
+            'x' | 'k' => {
+                self.kill(self.selected_text(kind));
+                self.delete_selection(kind);
+            }
+            'K' => {
+                self.kill(self.selected_text(kind));
+                self.delete_selection(kind);
+                self.update_highlighting();
+                self.mode = Mode::Default;
+                self.vars
+                    .insert(String::from("ret-to-nav"), BimVar::Bool(false));
+                return true;
+            }
+            '>' | '<' => {
+                self.indent_selection(c == '<');
+                self.cursor_pos = self.selection_bounds().0;
+            }
+            '~' => self.map_selection(kind, toggle_case),
+            'U' => self.map_selection(kind, |s| s.to_uppercase()),
+            'L' => self.map_selection(kind, |s| s.to_lowercase()),
+            'h' => {
+                if self.find_str.is_empty() {
+                    return true;
+                }
+                let (find, replace) = (self.find_str.clone(), self.replace_str.clone());
+                self.map_selection(kind, |s| s.replace(&find, &replace));
+            }
+            '!' => {
+                self.mode = Mode::Filter;
+                self.temp_str.clear();
+                return true;
+            }
+            _ => return false,
+        }
+        self.clamp_cursor();
+        self.update_highlighting();
+        self.mode = Mode::Nav;
+        true
+    }
+
+    /// Moves the cursor back into the contents.
+    pub fn clamp_cursor(&mut self) {
+        self.cursor_pos.line = self.cursor_pos.line.min(self.contents.len() - 1);
+        let len = self.contents[self.cursor_pos.line].chars().count();
+        self.cursor_pos.idx = self.cursor_pos.idx.min(len);
+    }
+}
+
+/// Handles keys in visual mode. Nav motions extend the selection.
+pub fn handle_visual(
+    buf: &mut Buffer,
+    kind: Select,
+    key: event::KeyEvent,
+    modifiers: &Mods,
+    height: usize,
+    width: usize,
+) {
+    match (modifiers, key.code) {
+        // Character searches and counts started in nav.
+        (Mods::None, KeyCode::Char(_)) if matches!(buf.temp_str.as_str(), "f" | "F") => {
+            handle_nav(buf, key, modifiers, height, width);
+        }
+        (Mods::None, KeyCode::Char(n)) if buf.temp_str == "r" => {
+            buf.temp_str.clear();
+            buf.map_selection(kind, |s| s.chars().map(|_| n).collect());
+            buf.update_highlighting();
+            buf.mode = Mode::Nav;
+        }
+        (Mods::None, KeyCode::Esc | KeyCode::Char('q')) => {
+            buf.mode = Mode::Nav;
+            buf.temp_str.clear();
+        }
+        (Mods::None, KeyCode::Char('v')) => buf.visual(Select::Char),
+        (Mods::None, KeyCode::Char('V')) => buf.visual(Select::Line),
+        (Mods::Ctrl, KeyCode::Char('v')) => buf.visual(Select::Block),
+        (Mods::None, KeyCode::Char(n)) if buf.temp_str == "\"" => {
+            buf.temp_str.clear();
+            buf.select_register(n);
+        }
+        (Mods::None, KeyCode::Char('r')) => buf.temp_str = String::from("r"),
+        (Mods::None, KeyCode::Char('"')) => buf.temp_str = String::from("\""),
+        (Mods::None, KeyCode::Char(c)) if buf.visual_operator(kind, c) => {
+            buf.temp_str.clear();
+        }
+        (
+            Mods::None,
+            KeyCode::Char(
+                'c'
+                | 'i'
+                | 'e'
+                | 'a'
+                | 'w'
+                | 'W'
+                | 't'
+                | 'b'
+                | 'u'
+                | 'd'
+                | '$'
+                | ';'
+                | 'n'
+                | 'p'
+                | 'f'
+                | 'F'
+                | '['
+                | ']'
+                | '%'
+                | '0'..='9',
+            )
+            | KeyCode::Home
+            | KeyCode::End,
+        )
+        | (Mods::Alt, KeyCode::Char('a' | 'e')) => {
+            handle_nav(buf, key, modifiers, height, width);
+        }
+        _ => {}
+    }
+}